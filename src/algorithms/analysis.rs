@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+///blocks needed before statistics of a candidate size are considered meaningful
+const MIN_BLOCKS: usize = 8;
+
+///multiples of the real block size score about as well as the size itself,
+///so a divisor of the best candidate wins if it is at least this good
+const MULTIPLE_TOLERANCE: f64 = 0.7;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockSizeCandidate {
+    pub block_size: usize,
+    pub score: f64,
+}
+
+///scores every block size in `2..=max_block_size` by building a contact matrix
+///of its positions and measuring how well a single permutation can chain them
+///back together. Candidates are sorted from most to least likely.
+///
+///Contact between positions `i` and `j` is the mutual information of the
+///elements found at `i` and `j` of the same block minus the mutual information
+///of `i` and `j` taken from neighbouring blocks, which cancels out the bias of
+///small samples. Positions that were adjacent in plaintext keep their bigram
+///dependence only when the guessed size is right (or a multiple of it).
+pub fn rank_block_sizes<T: Eq + Hash>(
+    data: &[T],
+    max_block_size: usize,
+) -> Vec<BlockSizeCandidate> {
+    let symbols = to_symbols(data);
+
+    let mut candidates = (2..=max_block_size)
+        .take_while(|&size| symbols.len() / size >= MIN_BLOCKS)
+        .map(|block_size| BlockSizeCandidate {
+            block_size,
+            score: score_block_size(&symbols, block_size),
+        })
+        .collect::<Vec<_>>();
+
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    candidates
+}
+
+///returns the most likely block size, preferring the smallest divisor of the
+///best candidate that scores comparably (multiples of the block size are also
+///consistent with the data)
+pub fn guess_block_size<T: Eq + Hash>(data: &[T], max_block_size: usize) -> Option<usize> {
+    pick_block_size(&rank_block_sizes(data, max_block_size))
+}
+
+pub fn pick_block_size(candidates: &[BlockSizeCandidate]) -> Option<usize> {
+    let best = candidates.first()?;
+    if best.score <= 0.0 {
+        return None;
+    }
+
+    candidates
+        .iter()
+        .filter(|c| best.block_size.is_multiple_of(c.block_size))
+        .filter(|c| c.score >= best.score * MULTIPLE_TOLERANCE)
+        .map(|c| c.block_size)
+        .min()
+}
+
+///largest block size that still leaves enough blocks in `length` elements
+pub fn max_testable_block_size(length: usize) -> usize {
+    length / MIN_BLOCKS
+}
+
+fn to_symbols<T: Eq + Hash>(data: &[T]) -> Vec<usize> {
+    let mut ids = HashMap::new();
+    data.iter()
+        .map(|item| {
+            let next = ids.len();
+            *ids.entry(item).or_insert(next)
+        })
+        .collect()
+}
+
+fn score_block_size(symbols: &[usize], block_size: usize) -> f64 {
+    let blocks = symbols.chunks_exact(block_size).collect::<Vec<_>>();
+    let mut counter = PairCounter::new(symbols.iter().max().map_or(0, |&m| m + 1));
+
+    let mut contacts = Vec::with_capacity(block_size * (block_size - 1));
+
+    for i in 0..block_size {
+        for j in 0..block_size {
+            if i == j {
+                continue;
+            }
+            let same_block = counter.mutual_information(blocks.iter().map(|b| (b[i], b[j])));
+            let next_block =
+                counter.mutual_information(blocks.windows(2).map(|w| (w[0][i], w[1][j])));
+            contacts.push((same_block - next_block, i, j));
+        }
+    }
+
+    best_chain_score(contacts, block_size)
+}
+
+///greedily links positions into a single chain (every position gets at most
+///one successor and one predecessor, no cycles) and returns mean contact of
+///chosen links
+fn best_chain_score(mut contacts: Vec<(f64, usize, usize)>, block_size: usize) -> f64 {
+    contacts.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut has_successor = vec![false; block_size];
+    let mut has_predecessor = vec![false; block_size];
+    let mut chain_of = (0..block_size).collect::<Vec<_>>();
+
+    fn find(chain_of: &mut [usize], mut i: usize) -> usize {
+        while chain_of[i] != i {
+            chain_of[i] = chain_of[chain_of[i]];
+            i = chain_of[i];
+        }
+        i
+    }
+
+    let mut total = 0.0;
+    let mut links = 0;
+
+    for (contact, i, j) in contacts {
+        if links == block_size - 1 {
+            break;
+        }
+        if has_successor[i] || has_predecessor[j] {
+            continue;
+        }
+        let (a, b) = (find(&mut chain_of, i), find(&mut chain_of, j));
+        if a == b {
+            continue;
+        }
+        chain_of[a] = b;
+        has_successor[i] = true;
+        has_predecessor[j] = true;
+        total += contact;
+        links += 1;
+    }
+
+    total / links as f64
+}
+
+///reusable buffers for counting symbol pairs, this runs for every pair of
+///positions of every candidate size
+struct PairCounter {
+    pairs: Vec<(usize, usize)>,
+    left: Vec<usize>,
+    right: Vec<usize>,
+}
+
+impl PairCounter {
+    fn new(alphabet_size: usize) -> Self {
+        PairCounter {
+            pairs: vec![],
+            left: vec![0; alphabet_size],
+            right: vec![0; alphabet_size],
+        }
+    }
+
+    fn mutual_information(&mut self, pairs: impl Iterator<Item = (usize, usize)>) -> f64 {
+        self.pairs.clear();
+        self.pairs.extend(pairs);
+
+        if self.pairs.is_empty() {
+            return 0.0;
+        }
+
+        for &(a, b) in &self.pairs {
+            self.left[a] += 1;
+            self.right[b] += 1;
+        }
+
+        self.pairs.sort_unstable();
+
+        let total = self.pairs.len() as f64;
+        let mut information = 0.0;
+
+        for run in self.pairs.chunk_by(|x, y| x == y) {
+            let (a, b) = run[0];
+            let count = run.len() as f64;
+            let expected = self.left[a] as f64 * self.right[b] as f64 / total;
+            information += count / total * (count / expected).log2();
+        }
+
+        for &(a, b) in &self.pairs {
+            self.left[a] = 0;
+            self.right[b] = 0;
+        }
+
+        information
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::algorithms::{
+        permutation::SimplePermutation, rail_fence::RailFenceCypher, stacked::StackedCypher,
+        vertical::VerticalPermutation,
+    };
+
+    use crate::algorithms::stacked::{EncryptionStyle::*, PadApproach::*};
+
+    use super::{guess_block_size, max_testable_block_size};
+
+    ///text with strong bigram structure: every letter is usually followed by
+    ///one of a few letters
+    fn markov_text(length: usize, seed: u64) -> String {
+        let alphabet = "etaoinshrdlu ".chars().collect::<Vec<_>>();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut current = 0;
+        (0..length)
+            .map(|_| {
+                current = if rng.gen_bool(0.8) {
+                    (current * 3 + 1) % alphabet.len()
+                } else {
+                    rng.gen_range(0..alphabet.len())
+                };
+                alphabet[current]
+            })
+            .collect()
+    }
+
+    fn guess(cypher: &StackedCypher, text: &str) -> Option<usize> {
+        let (_, encrypted) = cypher.encrypt(text.as_bytes()).unwrap();
+        let chars = String::from_utf8(encrypted)
+            .unwrap()
+            .chars()
+            .collect::<Vec<_>>();
+        guess_block_size(&chars, max_testable_block_size(chars.len()).min(24))
+    }
+
+    #[test]
+    fn detects_simple_permutation() {
+        let text = markov_text(2000, 1);
+
+        for indices in [
+            vec![3, 0, 4, 2, 1],
+            vec![6, 2, 5, 0, 3, 1, 4],
+            vec![11, 4, 9, 1, 7, 0, 10, 3, 8, 2, 6, 5],
+        ] {
+            let size = indices.len();
+            let mut cypher = StackedCypher::new();
+            cypher.push(Padding, Char, SimplePermutation::try_from(indices).unwrap());
+            assert_eq!(guess(&cypher, &text), Some(size));
+        }
+    }
+
+    #[test]
+    fn detects_vertical_and_rail_fence() {
+        let text = markov_text(2000, 2);
+
+        let mut vertical = StackedCypher::new();
+        vertical.push(
+            Unpadding,
+            Char,
+            VerticalPermutation::try_new(
+                2,
+                5,
+                SimplePermutation::try_from(vec![3, 0, 4, 1, 2]).unwrap(),
+            )
+            .unwrap(),
+        );
+        assert_eq!(guess(&vertical, &text), Some(10));
+
+        let mut rail = StackedCypher::new();
+        rail.push(Padding, Char, RailFenceCypher::try_new(3, 9).unwrap());
+        assert_eq!(guess(&rail, &text), Some(9));
+    }
+
+    #[test]
+    fn too_short_data_gives_no_guess() {
+        let data = "abcdefg".chars().collect::<Vec<_>>();
+        assert_eq!(guess_block_size(&data, 10), None);
+    }
+}
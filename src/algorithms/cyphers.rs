@@ -30,7 +30,16 @@ pub trait BlockDecrypt: IndexDecrypt {
 }
 
 pub trait PadEncrypt: BlockEncrypt {
-    fn encrypt_with_pad<T: ProvidesPad + Clone>(&self, data: &[T]) -> (usize, Vec<T>);
+    fn encrypt_with_pad<T: ProvidesPad + Clone>(&self, data: &[T]) -> (usize, Vec<T>) {
+        self.encrypt_with_filler(data, |reference: &T| reference.get_pad_value())
+    }
+
+    ///`filler` makes pad elements from the first element of the last block
+    fn encrypt_with_filler<T: Clone>(
+        &self,
+        data: &[T],
+        filler: impl FnMut(&T) -> T,
+    ) -> (usize, Vec<T>);
 }
 
 pub trait PadDecrypt: BlockDecrypt {
@@ -53,7 +62,11 @@ impl<C> PadEncrypt for C
 where
     C: BlockEncrypt,
 {
-    fn encrypt_with_pad<T: ProvidesPad + Clone>(&self, data: &[T]) -> (usize, Vec<T>) {
+    fn encrypt_with_filler<T: Clone>(
+        &self,
+        data: &[T],
+        mut filler: impl FnMut(&T) -> T,
+    ) -> (usize, Vec<T>) {
         let original_length = data.len();
         (
             original_length,
@@ -64,7 +77,9 @@ where
                         chunk
                     } else {
                         let elements_to_add = self.get_block_size() - chunk.len();
-                        let mut pad = chunk[0].get_pad(elements_to_add);
+                        let mut pad = std::iter::repeat_with(|| filler(&chunk[0]))
+                            .take(elements_to_add)
+                            .collect();
                         chunk.append(&mut pad);
                         chunk
                     }
@@ -81,7 +96,7 @@ impl<C: BlockDecrypt + Debug> PadDecrypt for C {
         data: &[T],
         original_size: usize,
     ) -> Result<Vec<T>, Box<dyn Error>> {
-        if data.len() % self.get_block_size() != 0 {
+        if !data.len().is_multiple_of(self.get_block_size()) {
             return Err(format!(
                 "failure while decrypting: got {} items, expected multiples of {} in {:?}",
                 data.len(),
@@ -147,6 +162,60 @@ impl<C: PadEncrypt + PadDecrypt> UnpadDecrypt for C {
     }
 }
 
+///ciphertext stealing: whole blocks are encrypted as usual, then the last
+///block is encrypted once more together with the incomplete tail, so every
+///element of the tail is moved by a full-size permutation. Messages shorter
+///than one block fall back to `UnpadEncrypt`
+pub trait StealEncrypt: UnpadEncrypt {
+    fn encrypt_stealing<T: Clone>(&self, data: &[T]) -> Vec<T> {
+        let block_size = self.get_block_size();
+        if data.len() < block_size {
+            return self.encrypt_unpad(data);
+        }
+
+        let whole = data.len() - data.len() % block_size;
+        let mut encrypted = data[..whole]
+            .chunks(block_size)
+            .flat_map(|chunk| self.encrypt_block(chunk.to_vec()))
+            .collect::<Vec<_>>();
+        encrypted.extend_from_slice(&data[whole..]);
+
+        if whole != data.len() {
+            let window = encrypted.split_off(data.len() - block_size);
+            encrypted.extend(self.encrypt_block(window));
+        }
+        encrypted
+    }
+}
+
+pub trait StealDecrypt: UnpadDecrypt {
+    fn decrypt_stealing<T: ProvidesPad + Clone>(&self, data: &[T]) -> Vec<T> {
+        let block_size = self.get_block_size();
+        if data.len() < block_size {
+            return self.decrypt_unpad(data);
+        }
+
+        let mut data = data.to_vec();
+        let whole = data.len() - data.len() % block_size;
+        if whole != data.len() {
+            let window = data.split_off(data.len() - block_size);
+            data.extend(self.decrypt_block(window));
+        }
+
+        let tail = data.split_off(whole);
+        let mut decrypted = data
+            .chunks(block_size)
+            .flat_map(|chunk| self.decrypt_block(chunk.to_vec()))
+            .collect::<Vec<_>>();
+        decrypted.extend(tail);
+        decrypted
+    }
+}
+
+impl<C: UnpadEncrypt> StealEncrypt for C {}
+
+impl<C: UnpadDecrypt> StealDecrypt for C {}
+
 pub trait PadCypher: PadEncrypt + PadDecrypt {}
 
 impl<C> PadCypher for C where C: PadEncrypt + PadDecrypt {}
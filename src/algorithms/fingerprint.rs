@@ -0,0 +1,138 @@
+use std::{error::Error, fmt::Display, io::BufWriter};
+
+use sha2::{Digest, Sha256};
+
+use super::{serialization::Serializer, stacked::StackedCypher};
+
+///hashed in front of every key, so that a fingerprint never equals a hash of
+///the same bytes made for another purpose. Checksums of database records and
+///shares have domains of their own for the same reason
+const DOMAIN: &[u8] = b"permutations key fingerprint v1\0";
+
+///how many leading bytes are rendered as words
+const WORDS: usize = 4;
+
+///one word for every byte value
+const WORD_LIST: [&str; 256] = [
+    "acid", "acorn", "actor", "adobe", "agent", "alarm", "album", "alley", "amber", "angel",
+    "anvil", "apple", "apron", "arena", "arrow", "atlas", "attic", "autumn", "bacon", "badge",
+    "bagel", "baker", "bamboo", "banjo", "barn", "basil", "beach", "beard", "beetle", "bell",
+    "bench", "berry", "bike", "bison", "blade", "blanket", "bloom", "boat", "bonnet", "boots",
+    "bottle", "bramble", "bread", "brick", "bridge", "brook", "broom", "bucket", "bugle", "butter",
+    "cabin", "cable", "cactus", "camel", "candle", "canoe", "canyon", "carpet", "carrot", "castle",
+    "cedar", "cello", "chalk", "cheese", "cherry", "chess", "cider", "circus", "clock", "cloud",
+    "clover", "cobra", "cocoa", "comet", "coral", "cotton", "cougar", "crab", "crane", "crayon",
+    "cricket", "crown", "cuckoo", "dagger", "daisy", "dancer", "delta", "desert", "diary", "dingo",
+    "dolphin", "donkey", "dragon", "drum", "eagle", "easel", "echo", "eclipse", "elbow", "ember",
+    "emerald", "engine", "falcon", "fable", "feather", "fern", "ferry", "fiddle", "fig", "flame",
+    "flute", "forest", "fossil", "fox", "frost", "galaxy", "garden", "garlic", "gecko", "geyser",
+    "ginger", "glacier", "globe", "goblet", "goose", "grape", "gravel", "guitar", "hammer",
+    "harbor", "harp", "hazel", "helmet", "heron", "honey", "hornet", "husky", "igloo", "iguana",
+    "island", "ivory", "jacket", "jaguar", "jasmine", "jelly", "jewel", "jigsaw", "juniper",
+    "kayak", "kettle", "kiwi", "koala", "ladder", "lagoon", "lantern", "larch", "lemon", "lentil",
+    "lily", "lizard", "lobster", "locket", "lotus", "lynx", "magnet", "mango", "maple", "marble",
+    "meadow", "melon", "mirror", "mitten", "monkey", "moose", "mosaic", "muffin", "mustard",
+    "nectar", "needle", "nickel", "nutmeg", "oasis", "ocean", "olive", "onion", "opal", "orange",
+    "orchid", "otter", "owl", "oyster", "paddle", "panda", "papaya", "parrot", "peach", "pebble",
+    "pepper", "piano", "pigeon", "pillow", "pine", "planet", "plum", "pony", "poppy", "potato",
+    "prism", "puffin", "pumpkin", "quail", "quartz", "quill", "rabbit", "radish", "raven", "reef",
+    "ribbon", "river", "robin", "rocket", "rose", "ruby", "saddle", "salmon", "sandal", "saturn",
+    "scarf", "shovel", "silver", "sketch", "sloth", "spider", "spruce", "squid", "stone", "sugar",
+    "summit", "swan", "tablet", "tiger", "tomato", "topaz", "tulip", "turtle", "valley", "velvet",
+    "violin", "walnut", "walrus", "wheat", "willow", "window", "wizard", "yacht", "zebra",
+];
+
+///short identifier of a key that does not reveal it. Two keys have the same
+///fingerprint exactly when they serialize to the same canonical form
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub [u8; 8]);
+
+impl Fingerprint {
+    pub fn of(cypher: &StackedCypher) -> Self {
+        let mut buffer = BufWriter::new(Vec::new());
+        //writing into memory cannot fail
+        Serializer::new(&mut buffer).write(cypher).unwrap();
+        let serialized = buffer.into_inner().unwrap();
+
+        let digest = Sha256::new()
+            .chain_update(DOMAIN)
+            .chain_update(&serialized)
+            .finalize();
+
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        Fingerprint(bytes)
+    }
+
+    pub fn to_hex(self) -> String {
+        self.0.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    pub fn from_hex(s: &str) -> Result<Self, Box<dyn Error>> {
+        if s.len() != 16 || !s.is_ascii() {
+            return Err(format!("fingerprint must be 16 hex digits, got \"{s}\"").into());
+        }
+        let mut bytes = [0u8; 8];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16)
+                .map_err(|_| format!("fingerprint must be 16 hex digits, got \"{s}\""))?;
+        }
+        Ok(Fingerprint(bytes))
+    }
+
+    ///first bytes as words, easier to compare when read aloud
+    pub fn to_words(self) -> String {
+        self.0[..WORDS]
+            .iter()
+            .map(|&b| WORD_LIST[b as usize])
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+}
+
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::algorithms::{
+        permutation::SimplePermutation, rail_fence::RailFenceCypher, stacked::StackedCypher,
+    };
+
+    use crate::algorithms::stacked::{EncryptionStyle::*, PadApproach::*};
+
+    use super::{Fingerprint, WORD_LIST};
+
+    fn get_cypher(permutation: Vec<usize>) -> StackedCypher {
+        let mut cypher = StackedCypher::new();
+        cypher.push(
+            Padding,
+            Char,
+            SimplePermutation::try_from(permutation).unwrap(),
+        );
+        cypher.push(Unpadding, Byte, RailFenceCypher::try_new(3, 8).unwrap());
+        cypher
+    }
+
+    #[test]
+    fn stable_and_distinct() {
+        let first = Fingerprint::of(&get_cypher(vec![2, 0, 1]));
+
+        assert_eq!(first, Fingerprint::of(&get_cypher(vec![2, 0, 1])));
+        assert_ne!(first, Fingerprint::of(&get_cypher(vec![1, 2, 0])));
+
+        assert_eq!(Fingerprint::from_hex(&first.to_hex()).unwrap(), first);
+        assert_eq!(first.to_words().split('-').count(), 4);
+        assert!(Fingerprint::from_hex("xyz").is_err());
+    }
+
+    #[test]
+    fn words_are_unique() {
+        assert_eq!(WORD_LIST.iter().collect::<HashSet<_>>().len(), 256);
+    }
+}
@@ -3,14 +3,37 @@ use std::error::Error;
 use rand::distributions::Distribution;
 use rand::distributions::Standard;
 use rand::prelude::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
 
 use super::permutation::SimplePermutation;
 use super::rail_fence::RailFenceCypher;
+use super::stacked::Algorithm;
+use super::strength;
 use super::vertical::VerticalPermutation;
 
+///attempts to find a strong permutation of a given size before giving up
+const STRONG_ATTEMPTS: usize = 1000;
+
+///same as [`Standard`], but keeps sampling until it gets a key that is not
+///considered weak by [`strength::is_weak`]
+pub struct Strong;
+
+impl Strong {
+    pub fn sample<T, R: Rng + ?Sized>(rng: &mut R) -> Result<T, Box<dyn Error>>
+    where
+        Standard: Distribution<T>,
+        T: Clone,
+        Algorithm: From<T>,
+    {
+        std::iter::repeat_with(|| rng.sample(Standard))
+            .take(STRONG_ATTEMPTS)
+            .find(|candidate: &T| !strength::is_weak(&candidate.clone().into()))
+            .ok_or_else(|| format!("no strong key found in {STRONG_ATTEMPTS} attempts").into())
+    }
+}
+
 impl Distribution<RailFenceCypher> for Standard {
-    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> RailFenceCypher {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> RailFenceCypher {
         let columns = rng.gen_range(4..=16);
         let rows = rng.gen_range(2..=(columns - 2));
         RailFenceCypher::try_new(rows, columns).unwrap()
@@ -18,7 +41,7 @@ impl Distribution<RailFenceCypher> for Standard {
 }
 
 impl Distribution<SimplePermutation> for Standard {
-    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> SimplePermutation {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> SimplePermutation {
         let size = rng.gen_range(2..=20);
         let mut indices = (0usize..size).collect::<Vec<_>>();
         indices.shuffle(rng);
@@ -27,7 +50,7 @@ impl Distribution<SimplePermutation> for Standard {
 }
 
 impl Distribution<VerticalPermutation> for Standard {
-    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> VerticalPermutation {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> VerticalPermutation {
         let columns = rng.gen_range(4..=16);
         let rows = rng.gen_range(2..=(columns - 2));
         let permutation = {
@@ -47,6 +70,24 @@ impl SimplePermutation {
         indices.shuffle(&mut thread_rng());
         Ok(SimplePermutation::try_from(indices).unwrap())
     }
+
+    pub fn random_strong_with_size(size: usize) -> Result<Self, Box<dyn Error>> {
+        let min_size = strength::min_strong_permutation_size();
+        if size < min_size {
+            return Err(format!(
+                "no permutation of size {size} is strong, at least {min_size} items are needed"
+            )
+            .into());
+        }
+        std::iter::repeat_with(|| Self::random_with_size(size))
+            .take(STRONG_ATTEMPTS)
+            .find(|candidate| {
+                candidate
+                    .as_ref()
+                    .map_or(true, |p| !strength::is_weak_permutation(p))
+            })
+            .unwrap_or_else(|| Err(format!("no strong permutation of size {size}").into()))
+    }
 }
 
 #[cfg(test)]
@@ -54,9 +95,12 @@ mod tests {
     use rand::{thread_rng, Rng};
 
     use crate::algorithms::{
-        permutation::SimplePermutation, rail_fence::RailFenceCypher, vertical::VerticalPermutation,
+        permutation::SimplePermutation, rail_fence::RailFenceCypher, strength,
+        vertical::VerticalPermutation,
     };
 
+    use super::Strong;
+
     #[test]
     fn generate_permutation() {
         for _ in 0..1000 {
@@ -77,4 +121,27 @@ mod tests {
             let _permutation: VerticalPermutation = thread_rng().gen();
         }
     }
+
+    #[test]
+    fn generate_strong() {
+        for _ in 0..1000 {
+            let permutation: SimplePermutation = Strong::sample(&mut thread_rng()).unwrap();
+            assert!(!strength::is_weak(&permutation.into()));
+            let vertical: VerticalPermutation = Strong::sample(&mut thread_rng()).unwrap();
+            assert!(!strength::is_weak(&vertical.into()));
+        }
+        //rows of a rail fence are found in a few attempts
+        assert!(Strong::sample::<RailFenceCypher, _>(&mut thread_rng()).is_err());
+
+        let min_size = strength::min_strong_permutation_size();
+        assert_eq!(min_size, 10);
+        for size in min_size..min_size + 8 {
+            let permutation = SimplePermutation::random_strong_with_size(size).unwrap();
+            assert!(!strength::is_weak_permutation(&permutation));
+        }
+
+        for size in 1..min_size {
+            assert!(SimplePermutation::random_strong_with_size(size).is_err());
+        }
+    }
 }
@@ -0,0 +1,281 @@
+//! Human-editable description of keys for JSON and TOML files. It mirrors
+//! `PadApproach`, `PadValue`, `EncryptionStyle` and parameters of every
+//! `Algorithm`, and is checked by the same constructors as typed keys
+
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    algorithms::{
+        decode::PermutationBlockDecoder,
+        permutation::SimplePermutation,
+        rail_fence::RailFenceCypher,
+        stacked::{
+            Algorithm, EncryptionStyle, GroupRemainder, Layer, PadApproach, PadValue, StackedCypher,
+        },
+        vertical::VerticalPermutation,
+    },
+    datastructs::MAX_BIT_FIELD_WIDTH,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayerSchema {
+    pub padding: PaddingSchema,
+    ///only used with `padding`
+    #[serde(default, skip_serializing_if = "PadSchema::is_default")]
+    pub pad: PadSchema,
+    pub style: StyleSchema,
+    pub algorithm: AlgorithmSchema,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaddingSchema {
+    Padding,
+    Unpadding,
+    Stealing,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PadSchema {
+    #[default]
+    Default,
+    ///code of the element: byte, character or bit
+    Fixed(u32),
+    Random,
+    Frequency,
+    Delimited,
+}
+
+impl PadSchema {
+    fn is_default(&self) -> bool {
+        *self == PadSchema::Default
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StyleSchema {
+    Bit,
+    Bits(usize),
+    Byte,
+    Char,
+    Group {
+        size: usize,
+        #[serde(default, skip_serializing_if = "RemainderSchema::is_reject")]
+        remainder: RemainderSchema,
+    },
+    Word,
+    Line,
+    Grapheme,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemainderSchema {
+    #[default]
+    Reject,
+    Pad(char),
+    Bypass,
+    Record,
+}
+
+impl RemainderSchema {
+    fn is_reject(&self) -> bool {
+        *self == RemainderSchema::Reject
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlgorithmSchema {
+    ///indices, as in `PERMUTATION(...)`
+    Permutation(Vec<usize>),
+    RailFence {
+        rows: usize,
+        columns: usize,
+    },
+    Vertical {
+        rows: usize,
+        columns: usize,
+        permutation: Vec<usize>,
+    },
+}
+
+pub fn describe(cypher: &StackedCypher) -> Vec<LayerSchema> {
+    cypher
+        .items()
+        .map(|layer| LayerSchema {
+            padding: match layer.pad_approach {
+                PadApproach::Padding => PaddingSchema::Padding,
+                PadApproach::Unpadding => PaddingSchema::Unpadding,
+                PadApproach::Stealing => PaddingSchema::Stealing,
+            },
+            pad: match (layer.pad_approach, layer.pad_value) {
+                (PadApproach::Padding, PadValue::Fixed(code)) => PadSchema::Fixed(code),
+                (PadApproach::Padding, PadValue::Random) => PadSchema::Random,
+                (PadApproach::Padding, PadValue::Frequency) => PadSchema::Frequency,
+                (PadApproach::Padding, PadValue::Delimited) => PadSchema::Delimited,
+                _ => PadSchema::Default,
+            },
+            style: match layer.style {
+                EncryptionStyle::Bit => StyleSchema::Bit,
+                EncryptionStyle::Bits(width) => StyleSchema::Bits(width),
+                EncryptionStyle::Byte => StyleSchema::Byte,
+                EncryptionStyle::Char => StyleSchema::Char,
+                EncryptionStyle::Group(size, remainder) => StyleSchema::Group {
+                    size,
+                    remainder: match remainder {
+                        GroupRemainder::Reject => RemainderSchema::Reject,
+                        GroupRemainder::Pad(filler) => RemainderSchema::Pad(filler),
+                        GroupRemainder::Bypass => RemainderSchema::Bypass,
+                        GroupRemainder::Record => RemainderSchema::Record,
+                    },
+                },
+                EncryptionStyle::Word => StyleSchema::Word,
+                EncryptionStyle::Line => StyleSchema::Line,
+                EncryptionStyle::Grapheme => StyleSchema::Grapheme,
+            },
+            algorithm: match &layer.algorithm {
+                Algorithm::Permutation(p) => {
+                    AlgorithmSchema::Permutation(p.get_inner().indices.clone())
+                }
+                Algorithm::RailFence(r) => AlgorithmSchema::RailFence {
+                    rows: r.get_inner().rows,
+                    columns: r.get_inner().columns,
+                },
+                Algorithm::Vertical(v) => AlgorithmSchema::Vertical {
+                    rows: v.get_inner().rows,
+                    columns: v.get_inner().columns,
+                    permutation: v.get_inner().permutation.indices.clone(),
+                },
+            },
+        })
+        .collect()
+}
+
+pub fn build(layers: &[LayerSchema]) -> Result<StackedCypher, Box<dyn Error>> {
+    let mut cypher = StackedCypher::new();
+
+    for (i, layer) in layers.iter().enumerate() {
+        let layer = build_layer(layer).map_err(|e| format!("layer {i}: {e}"))?;
+        cypher.push_layer(layer);
+    }
+    Ok(cypher)
+}
+
+fn build_layer(layer: &LayerSchema) -> Result<Layer, Box<dyn Error>> {
+    let pad_approach = match layer.padding {
+        PaddingSchema::Padding => PadApproach::Padding,
+        PaddingSchema::Unpadding => PadApproach::Unpadding,
+        PaddingSchema::Stealing => PadApproach::Stealing,
+    };
+    if pad_approach != PadApproach::Padding && !layer.pad.is_default() {
+        return Err("pad is only used with padding".into());
+    }
+    let pad_value = match layer.pad {
+        PadSchema::Default => PadValue::Default,
+        PadSchema::Fixed(code) => PadValue::Fixed(code),
+        PadSchema::Random => PadValue::Random,
+        PadSchema::Frequency => PadValue::Frequency,
+        PadSchema::Delimited => PadValue::Delimited,
+    };
+
+    let style = match layer.style {
+        StyleSchema::Bit => EncryptionStyle::Bit,
+        StyleSchema::Bits(width) => {
+            if !(1..=MAX_BIT_FIELD_WIDTH).contains(&width) {
+                return Err(format!("invalid bit field width {width}").into());
+            }
+            EncryptionStyle::Bits(width)
+        }
+        StyleSchema::Byte => EncryptionStyle::Byte,
+        StyleSchema::Char => EncryptionStyle::Char,
+        StyleSchema::Group { size: 0, .. } => return Err("invalid group size 0".into()),
+        StyleSchema::Group { size, remainder } => EncryptionStyle::Group(
+            size,
+            match remainder {
+                RemainderSchema::Reject => GroupRemainder::Reject,
+                RemainderSchema::Pad(filler) => GroupRemainder::Pad(filler),
+                RemainderSchema::Bypass => GroupRemainder::Bypass,
+                RemainderSchema::Record => GroupRemainder::Record,
+            },
+        ),
+        StyleSchema::Word => EncryptionStyle::Word,
+        StyleSchema::Line => EncryptionStyle::Line,
+        StyleSchema::Grapheme => EncryptionStyle::Grapheme,
+    };
+
+    let permutation = |indices: &Vec<usize>| {
+        SimplePermutation::try_from(indices.clone())
+            .ok_or_else(|| <Box<dyn Error>>::from("misconfigured permutation"))
+    };
+    let algorithm = match &layer.algorithm {
+        AlgorithmSchema::Permutation(indices) => {
+            Algorithm::Permutation(PermutationBlockDecoder::new(permutation(indices)?))
+        }
+        AlgorithmSchema::RailFence { rows, columns } => Algorithm::RailFence(
+            PermutationBlockDecoder::new(RailFenceCypher::try_new(*rows, *columns)?),
+        ),
+        AlgorithmSchema::Vertical {
+            rows,
+            columns,
+            permutation: indices,
+        } => Algorithm::Vertical(PermutationBlockDecoder::new(VerticalPermutation::try_new(
+            *rows,
+            *columns,
+            permutation(indices)?,
+        )?)),
+    };
+
+    Ok(Layer::new(pad_approach, style, algorithm).with_pad_value(pad_value))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::algorithms::{
+        permutation::SimplePermutation,
+        rail_fence::RailFenceCypher,
+        stacked::{
+            EncryptionStyle::*, GroupRemainder, Layer, PadApproach::*, PadValue, StackedCypher,
+        },
+    };
+
+    use super::{build, describe, AlgorithmSchema, PaddingSchema, RemainderSchema, StyleSchema};
+
+    #[test]
+    fn round_trip_and_validation() {
+        let mut cypher = StackedCypher::new();
+        cypher.push(
+            Padding,
+            Group(3, GroupRemainder::Pad('x')),
+            SimplePermutation::try_from(vec![2, 0, 1]).unwrap(),
+        );
+        cypher.push(Unpadding, Bits(5), RailFenceCypher::try_new(2, 3).unwrap());
+        let mut layers = describe(&cypher);
+        assert_eq!(build(&layers).unwrap(), cypher);
+
+        layers[0].algorithm = AlgorithmSchema::Permutation(vec![0, 0, 1]);
+        assert!(build(&layers).is_err());
+
+        let mut layers = describe(&cypher);
+        layers[0].style = StyleSchema::Group {
+            size: 0,
+            remainder: RemainderSchema::Bypass,
+        };
+        assert!(build(&layers).is_err());
+
+        let mut fixed = StackedCypher::new();
+        fixed.push_layer(
+            Layer::new(Padding, Byte, SimplePermutation::trivial(2))
+                .with_pad_value(PadValue::Fixed(7)),
+        );
+        let mut layers = describe(&fixed);
+        assert_eq!(build(&layers).unwrap(), fixed);
+        layers[0].padding = PaddingSchema::Stealing;
+        assert!(build(&layers).is_err());
+    }
+}
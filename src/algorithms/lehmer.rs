@@ -0,0 +1,222 @@
+use std::error::Error;
+
+use super::permutation::SimplePermutation;
+
+const BASE62: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+///largest permutation stored as a rank. Ranking takes quadratic time, and
+///a size read from a file must not allocate without bound
+pub const MAX_RANKED_SIZE: usize = 1 << 20;
+
+///unsigned integer of arbitrary size. Ranks only need multiplication, addition
+///and division by machine-sized numbers, so nothing else is implemented
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BigUint {
+    //little endian, without trailing zero limbs
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    pub fn zero() -> Self {
+        BigUint { limbs: vec![] }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn mul_add(&mut self, multiplier: u32, addend: u32) {
+        let mut carry = addend as u64;
+        for limb in self.limbs.iter_mut() {
+            let value = *limb as u64 * multiplier as u64 + carry;
+            *limb = value as u32;
+            carry = value >> 32;
+        }
+        if carry != 0 {
+            self.limbs.push(carry as u32);
+        }
+        self.normalize();
+    }
+
+    ///divides in place and returns remainder
+    pub fn div_rem(&mut self, divisor: u32) -> u32 {
+        assert_ne!(divisor, 0);
+        let mut remainder = 0u64;
+        for limb in self.limbs.iter_mut().rev() {
+            let value = (remainder << 32) | *limb as u64;
+            *limb = (value / divisor as u64) as u32;
+            remainder = value % divisor as u64;
+        }
+        self.normalize();
+        remainder as u32
+    }
+
+    pub fn to_base62(&self) -> String {
+        if self.is_zero() {
+            return "0".to_string();
+        }
+        let mut value = self.clone();
+        let mut digits = vec![];
+        while !value.is_zero() {
+            digits.push(BASE62[value.div_rem(62) as usize]);
+        }
+        digits.reverse();
+        //alphabet is ascii
+        String::from_utf8(digits).unwrap()
+    }
+
+    pub fn from_base62(s: &str) -> Result<Self, Box<dyn Error>> {
+        if s.is_empty() {
+            return Err("empty base62 number".into());
+        }
+        let mut value = BigUint::zero();
+        for c in s.bytes() {
+            let digit = BASE62
+                .iter()
+                .position(|&d| d == c)
+                .ok_or_else(|| format!("invalid base62 digit '{}'", c as char))?;
+            value.mul_add(62, digit as u32);
+        }
+        Ok(value)
+    }
+
+    fn normalize(&mut self) {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+    }
+}
+
+impl SimplePermutation {
+    ///position of permutation in lexicographic order of all permutations of
+    ///its size, computed from Lehmer code in factorial number system
+    pub fn rank(&self) -> BigUint {
+        let size = self.indices.len();
+        let mut rank = BigUint::zero();
+
+        for (i, &value) in self.indices.iter().enumerate() {
+            let digit = self.indices[i + 1..].iter().filter(|&&v| v < value).count();
+            rank.mul_add((size - i) as u32, digit as u32);
+        }
+
+        rank
+    }
+
+    pub fn unrank(size: usize, rank: &BigUint) -> Result<Self, Box<dyn Error>> {
+        if size == 0 {
+            return Err("size of permutation must be greater than zero".into());
+        }
+        if size > MAX_RANKED_SIZE {
+            return Err(format!(
+                "ranked permutation of size {size} is too large, at most {MAX_RANKED_SIZE} is supported"
+            )
+            .into());
+        }
+
+        let mut rank = rank.clone();
+        let mut digits = (1..=size)
+            .map(|radix| rank.div_rem(radix as u32) as usize)
+            .collect::<Vec<_>>();
+        digits.reverse();
+
+        if !rank.is_zero() {
+            return Err(format!("rank is too large for permutation of size {size}").into());
+        }
+
+        let mut available = (0..size).collect::<Vec<_>>();
+        let indices = digits.into_iter().map(|d| available.remove(d)).collect();
+
+        Ok(SimplePermutation { indices })
+    }
+
+    ///`size.rank` with rank in base62
+    pub fn to_compact_string(&self) -> String {
+        format!("{}.{}", self.indices.len(), self.rank().to_base62())
+    }
+
+    pub fn from_compact_string(s: &str) -> Result<Self, Box<dyn Error>> {
+        let (size, rank) = s
+            .split_once('.')
+            .ok_or_else(|| format!("compact permutation \"{s}\" must look like size.rank"))?;
+        let size = size.parse::<usize>()?;
+        Self::unrank(size, &BigUint::from_base62(rank)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::algorithms::permutation::SimplePermutation;
+
+    use super::{BigUint, MAX_RANKED_SIZE};
+
+    #[test]
+    fn base62() {
+        let mut value = BigUint::zero();
+        assert_eq!(value.to_base62(), "0");
+        value.mul_add(1, 61);
+        assert_eq!(value.to_base62(), "z");
+        value.mul_add(62, 1);
+        assert_eq!(value.to_base62(), "z1");
+        assert_eq!(BigUint::from_base62("z1").unwrap(), value);
+        assert!(BigUint::from_base62("a-b").is_err());
+    }
+
+    #[test]
+    fn lexicographic_ranks() {
+        let ranked = (0..6)
+            .map(|rank| {
+                let mut value = BigUint::zero();
+                value.mul_add(1, rank);
+                SimplePermutation::unrank(3, &value).unwrap().indices
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            ranked,
+            vec![
+                vec![0, 1, 2],
+                vec![0, 2, 1],
+                vec![1, 0, 2],
+                vec![1, 2, 0],
+                vec![2, 0, 1],
+                vec![2, 1, 0]
+            ]
+        );
+
+        let mut too_large = BigUint::zero();
+        too_large.mul_add(1, 6);
+        assert!(SimplePermutation::unrank(3, &too_large).is_err());
+    }
+
+    #[test]
+    fn compact_round_trip() {
+        for size in [1, 2, 13, 100, 1000] {
+            let permutation = SimplePermutation::random_with_size(size).unwrap();
+            let compact = permutation.to_compact_string();
+            assert_eq!(
+                SimplePermutation::from_compact_string(&compact).unwrap(),
+                permutation
+            );
+        }
+
+        assert_eq!(
+            SimplePermutation::trivial(5).to_compact_string(),
+            "5.0".to_string()
+        );
+        assert!(SimplePermutation::from_compact_string("5").is_err());
+    }
+
+    #[test]
+    fn huge_sizes_are_rejected() {
+        for compact in [
+            format!("{}.0", MAX_RANKED_SIZE + 1),
+            format!("{}.0", u32::MAX as u64 + 2),
+            format!("{}.1", usize::MAX),
+        ] {
+            let error = SimplePermutation::from_compact_string(&compact)
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains("too large"), "{error}");
+        }
+    }
+}
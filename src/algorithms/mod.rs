@@ -1,11 +1,16 @@
 #![allow(dead_code)]
+pub mod analysis;
 pub mod cyphers;
 pub mod decode;
+pub mod fingerprint;
 pub mod generation;
+pub mod interchange;
+pub mod lehmer;
 pub mod permutation;
 pub mod rail_fence;
 pub mod serialization;
 pub mod stacked;
+pub mod strength;
 pub mod vertical;
 
 ///accepts `indices` which are used as follows:
@@ -18,7 +23,7 @@ pub(super) fn move_by_indices<T>(data: Vec<T>, indices: &[usize]) -> Vec<T> {
         .take(data.len())
         .collect();
 
-    for (&index, value) in indices.iter().zip(data.into_iter()) {
+    for (&index, value) in indices.iter().zip(data) {
         items[index].write(value);
     }
 
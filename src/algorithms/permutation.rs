@@ -1,4 +1,6 @@
 use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::Display;
 
 use crate::algorithms::cyphers::{BlockEncrypt, Blocky, IndexEncrypt};
 
@@ -29,6 +31,171 @@ impl SimplePermutation {
     pub fn trivial(size: usize) -> Self {
         Self::try_from((0..size).collect()).unwrap()
     }
+
+    ///builds permutation from cycle notation, where cycle `(0 2 1)` moves item
+    ///from position 0 to 2, from 2 to 1 and from 1 back to 0. Positions not
+    ///mentioned stay in place. Size defaults to the largest mentioned position + 1
+    pub fn from_cycles(cycles: &[Vec<usize>], size: Option<usize>) -> Result<Self, Box<dyn Error>> {
+        let largest = cycles.iter().flatten().max().map_or(0, |&m| m + 1);
+        let size = size.unwrap_or(largest);
+
+        if size == 0 {
+            return Err("size of permutation must be greater than zero".into());
+        }
+
+        if largest > size {
+            return Err(format!(
+                "cycle mentions position {} in permutation of size {size}",
+                largest - 1
+            )
+            .into());
+        }
+
+        let mut indices = (0..size).collect::<Vec<_>>();
+        let mut seen = HashSet::new();
+
+        for cycle in cycles {
+            for (i, &position) in cycle.iter().enumerate() {
+                if !seen.insert(position) {
+                    return Err(format!(
+                        "position {position} is mentioned more than once in cycles"
+                    )
+                    .into());
+                }
+                indices[position] = cycle[(i + 1) % cycle.len()];
+            }
+        }
+
+        Ok(SimplePermutation { indices })
+    }
+
+    ///all cycles of length two or more, each starting from its smallest position
+    pub fn cycles(&self) -> Vec<Vec<usize>> {
+        let mut visited = vec![false; self.indices.len()];
+        let mut cycles = vec![];
+
+        for start in 0..self.indices.len() {
+            if visited[start] {
+                continue;
+            }
+            let mut cycle = vec![];
+            let mut position = start;
+            while !visited[position] {
+                visited[position] = true;
+                cycle.push(position);
+                position = self.indices[position];
+            }
+            if cycle.len() > 1 {
+                cycles.push(cycle);
+            }
+        }
+
+        cycles
+    }
+
+    ///permutation that applies `self` and then `then`
+    pub fn compose(&self, then: &SimplePermutation) -> Result<Self, Box<dyn Error>> {
+        if self.indices.len() != then.indices.len() {
+            return Err(format!(
+                "cannot compose permutations of sizes {} and {}",
+                self.indices.len(),
+                then.indices.len()
+            )
+            .into());
+        }
+
+        Ok(SimplePermutation {
+            indices: self.indices.iter().map(|&i| then.indices[i]).collect(),
+        })
+    }
+
+    pub fn invert(&self) -> Self {
+        SimplePermutation {
+            indices: Self::inverse(&self.indices),
+        }
+    }
+
+    ///applies permutation `exponent` times, negative exponents apply the inverse
+    pub fn pow(&self, exponent: i64) -> Self {
+        let mut indices = self.indices.clone();
+
+        for cycle in self.cycles() {
+            let length = cycle.len() as i64;
+            let shift = exponent.rem_euclid(length) as usize;
+            for (i, &position) in cycle.iter().enumerate() {
+                indices[position] = cycle[(i + shift) % cycle.len()];
+            }
+        }
+
+        SimplePermutation { indices }
+    }
+
+    ///smallest number of applications that gives identity, `None` if it does not fit into u128
+    pub fn order(&self) -> Option<u128> {
+        fn gcd(a: u128, b: u128) -> u128 {
+            if b == 0 {
+                a
+            } else {
+                gcd(b, a % b)
+            }
+        }
+
+        self.cycles().iter().try_fold(1u128, |order, cycle| {
+            let length = cycle.len() as u128;
+            (order / gcd(order, length)).checked_mul(length)
+        })
+    }
+
+    ///permutation is even if it can be made from even number of transpositions
+    pub fn is_even(&self) -> bool {
+        self.cycles().iter().map(|c| c.len() - 1).sum::<usize>() % 2 == 0
+    }
+
+    pub fn sign(&self) -> i8 {
+        if self.is_even() {
+            1
+        } else {
+            -1
+        }
+    }
+
+    pub fn fixed_points(&self) -> Vec<usize> {
+        self.indices
+            .iter()
+            .enumerate()
+            .filter(|(i, &target)| *i == target)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.indices
+            .iter()
+            .enumerate()
+            .all(|(i, &target)| i == target)
+    }
+}
+
+///cycle notation, `()` for identity
+impl Display for SimplePermutation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cycles = self.cycles();
+        if cycles.is_empty() {
+            return write!(f, "()");
+        }
+        for cycle in cycles {
+            write!(
+                f,
+                "({})",
+                cycle
+                    .iter()
+                    .map(|i| i.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )?;
+        }
+        Ok(())
+    }
 }
 
 impl Blocky for SimplePermutation {
@@ -93,6 +260,56 @@ mod test {
         }
     }
 
+    #[test]
+    fn cycle_notation() {
+        let permutation =
+            SimplePermutation::from_cycles(&[vec![0, 2, 1], vec![3, 4]], None).unwrap();
+
+        assert_eq!(permutation.indices, vec![2, 0, 1, 4, 3]);
+        assert_eq!(permutation.cycles(), vec![vec![0, 2, 1], vec![3, 4]]);
+        assert_eq!(permutation.to_string(), "(0 2 1)(3 4)");
+
+        let padded = SimplePermutation::from_cycles(&[vec![1, 0]], Some(4)).unwrap();
+        assert_eq!(padded.indices, vec![1, 0, 2, 3]);
+        assert_eq!(padded.fixed_points(), vec![2, 3]);
+
+        assert_eq!(SimplePermutation::trivial(3).to_string(), "()");
+
+        assert!(SimplePermutation::from_cycles(&[vec![0, 1], vec![1, 2]], None).is_err());
+        assert!(SimplePermutation::from_cycles(&[vec![0, 5]], Some(3)).is_err());
+    }
+
+    #[test]
+    fn algebra() {
+        let permutation =
+            SimplePermutation::from_cycles(&[vec![0, 2, 1], vec![3, 4]], None).unwrap();
+
+        assert_eq!(permutation.order(), Some(6));
+        assert!(!permutation.is_even());
+        assert_eq!(permutation.sign(), -1);
+        assert!(permutation.pow(6).is_identity());
+        assert_eq!(permutation.pow(-1), permutation.invert());
+        assert_eq!(
+            permutation.pow(2),
+            permutation.compose(&permutation).unwrap()
+        );
+        assert!(permutation
+            .compose(&permutation.invert())
+            .unwrap()
+            .is_identity());
+        assert!(permutation.compose(&SimplePermutation::trivial(4)).is_err());
+
+        let data = "abcde".chars().collect::<Vec<_>>();
+        let other = SimplePermutation::try_from(vec![4, 3, 0, 1, 2]).unwrap();
+        assert_eq!(
+            permutation
+                .compose(&other)
+                .unwrap()
+                .encrypt_block(data.clone()),
+            other.encrypt_block(permutation.encrypt_block(data))
+        );
+    }
+
     #[test]
     fn random_test_unpadded() {
         let data: Vec<usize> = (0..100).collect();
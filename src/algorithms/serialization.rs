@@ -3,40 +3,75 @@ use std::{
     io::{self, BufRead, Write},
 };
 
-use crate::algorithms::{
-    decode::PermutationBlockDecoder,
-    permutation::SimplePermutation,
-    stacked::{Algorithm, PadApproach, StackedCypher},
+use crate::{
+    algorithms::{
+        decode::PermutationBlockDecoder,
+        lehmer::MAX_RANKED_SIZE,
+        permutation::SimplePermutation,
+        stacked::{Algorithm, GroupRemainder, Layer, PadApproach, PadValue, StackedCypher},
+    },
+    datastructs::MAX_BIT_FIELD_WIDTH,
 };
 
 use super::{rail_fence::RailFenceCypher, stacked::EncryptionStyle, vertical::VerticalPermutation};
 
 pub struct Serializer<'w, W: Write> {
     writer: &'w mut W,
+    compact: bool,
 }
 
 impl<'w, W: Write> Serializer<'w, W> {
     pub fn new(target: &'w mut W) -> Self {
-        Self { writer: target }
+        Self {
+            writer: target,
+            compact: false,
+        }
+    }
+
+    ///write simple permutations as their rank instead of list of indices
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
     }
 
     pub fn write(&mut self, cypher: &StackedCypher) -> io::Result<()> {
         self.write_number(cypher.len())?;
 
-        for (pad, style, algorithm) in cypher.items() {
-            match pad {
-                PadApproach::Padding => self.write_str("padding")?,
-                PadApproach::Unpadding => self.write_str("unpadding")?,
+        for layer in cypher.items() {
+            match (layer.pad_approach, layer.pad_value) {
+                (PadApproach::Padding, PadValue::Default) => self.write_str("padding")?,
+                (PadApproach::Padding, PadValue::Fixed(code)) => {
+                    self.write_str("padding-fixed")?;
+                    self.write_number(code as usize)?;
+                }
+                (PadApproach::Padding, PadValue::Random) => self.write_str("padding-random")?,
+                (PadApproach::Padding, PadValue::Delimited) => {
+                    self.write_str("padding-delimited")?
+                }
+                (PadApproach::Padding, PadValue::Frequency) => {
+                    self.write_str("padding-frequency")?
+                }
+                (PadApproach::Unpadding, _) => self.write_str("unpadding")?,
+                (PadApproach::Stealing, _) => self.write_str("stealing")?,
             }
 
-            self.write_str(&match *style {
+            self.write_str(&match layer.style {
                 EncryptionStyle::Bit => "bit".to_string(),
+                EncryptionStyle::Bits(w) => format!("bits {w}"),
                 EncryptionStyle::Byte => "byte".to_string(),
                 EncryptionStyle::Char => "char".to_string(),
-                EncryptionStyle::Group(g) => format!("group {g}"),
+                EncryptionStyle::Group(g, GroupRemainder::Reject) => format!("group {g}"),
+                EncryptionStyle::Group(g, GroupRemainder::Pad(filler)) => {
+                    format!("group-pad {g} {}", filler as u32)
+                }
+                EncryptionStyle::Group(g, GroupRemainder::Bypass) => format!("group-bypass {g}"),
+                EncryptionStyle::Group(g, GroupRemainder::Record) => format!("group-record {g}"),
+                EncryptionStyle::Word => "word".to_string(),
+                EncryptionStyle::Line => "line".to_string(),
+                EncryptionStyle::Grapheme => "grapheme".to_string(),
             })?;
 
-            self.write_permutation(algorithm)?;
+            self.write_permutation(&layer.algorithm)?;
         }
 
         Ok(())
@@ -58,8 +93,13 @@ impl<'w, W: Write> Serializer<'w, W> {
     }
 
     fn write_simple_permutation(&mut self, p: &SimplePermutation) -> io::Result<()> {
-        self.write_str("simple")?;
-        self.write_array(&p.indices)
+        if self.compact && p.indices.len() <= MAX_RANKED_SIZE {
+            self.write_str("ranked")?;
+            self.write_str(&p.to_compact_string())
+        } else {
+            self.write_str("simple")?;
+            self.write_array(&p.indices)
+        }
     }
 
     fn write_rail_fence(&mut self, p: &RailFenceCypher) -> io::Result<()> {
@@ -99,26 +139,56 @@ impl<R: BufRead> Deserializer<R> {
         let mut res = StackedCypher::new();
 
         for _ in 0..size {
-            let pad = match self.read_string()?.as_str() {
-                "padding" => PadApproach::Padding,
-                "unpadding" => PadApproach::Unpadding,
+            let (pad, pad_value) = match self.read_string()?.as_str() {
+                "padding" => (PadApproach::Padding, PadValue::Default),
+                "padding-fixed" => {
+                    let code = u32::try_from(self.read_number()?)?;
+                    (PadApproach::Padding, PadValue::Fixed(code))
+                }
+                "padding-random" => (PadApproach::Padding, PadValue::Random),
+                "padding-frequency" => (PadApproach::Padding, PadValue::Frequency),
+                "padding-delimited" => (PadApproach::Padding, PadValue::Delimited),
+                "unpadding" => (PadApproach::Unpadding, PadValue::Default),
+                "stealing" => (PadApproach::Stealing, PadValue::Default),
                 other => return Err(format!("unknown padding type {other}").into()),
             };
 
             let style = match self.read_string()?.as_str() {
                 "bit" => EncryptionStyle::Bit,
+                "bits" => {
+                    let width = self.read_number()?;
+                    if !(1..=MAX_BIT_FIELD_WIDTH).contains(&width) {
+                        return Err(format!("invalid bit field width {width}").into());
+                    }
+                    EncryptionStyle::Bits(width)
+                }
                 "byte" => EncryptionStyle::Byte,
                 "char" => EncryptionStyle::Char,
                 "group" => {
-                    let size = self.read_number()?;
-                    EncryptionStyle::Group(size)
+                    let size = self.read_group_size()?;
+                    EncryptionStyle::Group(size, GroupRemainder::Reject)
+                }
+                "group-pad" => {
+                    let size = self.read_group_size()?;
+                    let filler = char::from_u32(self.read_number()? as u32)
+                        .ok_or("invalid filler character of group")?;
+                    EncryptionStyle::Group(size, GroupRemainder::Pad(filler))
+                }
+                "group-bypass" => {
+                    EncryptionStyle::Group(self.read_group_size()?, GroupRemainder::Bypass)
+                }
+                "group-record" => {
+                    EncryptionStyle::Group(self.read_group_size()?, GroupRemainder::Record)
                 }
+                "word" => EncryptionStyle::Word,
+                "line" => EncryptionStyle::Line,
+                "grapheme" => EncryptionStyle::Grapheme,
                 other => return Err(format!("unknown encryption style {other}").into()),
             };
 
             let algo = self.read_permutation()?;
 
-            res.push(pad, style, algo);
+            res.push_layer(Layer::new(pad, style, algo).with_pad_value(pad_value));
         }
 
         Ok(res)
@@ -137,14 +207,25 @@ impl<R: BufRead> Deserializer<R> {
         self.read_string()?.parse::<usize>().map_err(|e| e.into())
     }
 
+    fn read_group_size(&mut self) -> Result<usize, Box<dyn Error>> {
+        match self.read_number()? {
+            0 => Err("invalid group size 0".into()),
+            size => Ok(size),
+        }
+    }
+
     fn read_array(&mut self) -> Result<Vec<usize>, Box<dyn Error>> {
         let size = self.read_number()?;
         (0..size).map(|_| self.read_number()).collect()
     }
 
-    fn read_simple_permutation(&mut self) -> Result<SimplePermutation, Box<dyn Error>> {
-        SimplePermutation::try_from(self.read_array()?)
-            .ok_or_else(|| "failed to read simple permutation".into())
+    fn read_simple_permutation(&mut self, tag: &str) -> Result<SimplePermutation, Box<dyn Error>> {
+        match tag {
+            "simple" => SimplePermutation::try_from(self.read_array()?)
+                .ok_or_else(|| "failed to read simple permutation".into()),
+            "ranked" => SimplePermutation::from_compact_string(&self.read_string()?),
+            other => Err(format!("unknown simple permutation type {other}").into()),
+        }
     }
 
     fn read_rail_fence(&mut self) -> Result<RailFenceCypher, Box<dyn Error>> {
@@ -154,8 +235,8 @@ impl<R: BufRead> Deserializer<R> {
     fn read_vertical(&mut self) -> Result<VerticalPermutation, Box<dyn Error>> {
         let rows = self.read_number()?;
         let columns = self.read_number()?;
-        let _tag = self.read_string()?;
-        let permutation = self.read_simple_permutation()?;
+        let tag = self.read_string()?;
+        let permutation = self.read_simple_permutation(&tag)?;
 
         VerticalPermutation::try_new(rows, columns, permutation)
     }
@@ -164,8 +245,8 @@ impl<R: BufRead> Deserializer<R> {
         let tag = self.read_string()?;
 
         Ok(match tag.as_str() {
-            "simple" => Algorithm::Permutation(PermutationBlockDecoder::new(
-                self.read_simple_permutation()?,
+            "simple" | "ranked" => Algorithm::Permutation(PermutationBlockDecoder::new(
+                self.read_simple_permutation(&tag)?,
             )),
             "vertical" => Algorithm::Vertical(PermutationBlockDecoder::new(self.read_vertical()?)),
             "rail" => Algorithm::RailFence(PermutationBlockDecoder::new(self.read_rail_fence()?)),
@@ -188,6 +269,7 @@ mod tests {
     use super::{Deserializer, Serializer};
 
     use crate::algorithms::stacked::EncryptionStyle::*;
+    use crate::algorithms::stacked::GroupRemainder;
     use crate::algorithms::stacked::PadApproach::*;
 
     #[test]
@@ -244,5 +326,87 @@ mod tests {
         let produced_output = produced.encrypt(&(0..255u8).collect::<Vec<_>>()).unwrap();
 
         assert_eq!(expected_output, produced_output);
+
+        for style in [
+            "group 0",
+            "group-pad 0 120",
+            "group-bypass 0",
+            "group-record 0",
+        ] {
+            let source = format!("1 padding {style} simple 2 1 0 ");
+            assert!(
+                Deserializer::new(source.as_bytes()).read().is_err(),
+                "{style}"
+            );
+        }
+    }
+
+    #[test]
+    fn should_round_trip_compact() {
+        let mut cypher = StackedCypher::new();
+
+        cypher.push(
+            Padding,
+            Char,
+            SimplePermutation::try_from(vec![3, 2, 0, 1]).unwrap(),
+        );
+        cypher.push(
+            Unpadding,
+            Group(3, GroupRemainder::Pad(' ')),
+            VerticalPermutation::try_new(
+                2,
+                4,
+                SimplePermutation::try_from(vec![1, 0, 3, 2]).unwrap(),
+            )
+            .unwrap(),
+        );
+
+        let expected =
+            "2 padding char ranked 4.M unpadding group-pad 3 32 vertical 2 4 ranked 4.7 ";
+        let mut buf = BufWriter::new(Vec::new());
+        Serializer::new(&mut buf)
+            .compact(true)
+            .write(&cypher)
+            .unwrap();
+        let written = String::from_utf8(buf.into_inner().unwrap()).unwrap();
+        assert_eq!(written, expected);
+
+        let produced = Deserializer::new(written.as_bytes()).read().unwrap();
+        assert_eq!(produced, cypher);
+    }
+
+    #[test]
+    fn should_round_trip_pad_values() {
+        use crate::algorithms::stacked::{Layer, PadValue};
+
+        let mut cypher = StackedCypher::new();
+        for pad_value in [
+            PadValue::Default,
+            PadValue::Fixed(88),
+            PadValue::Random,
+            PadValue::Frequency,
+        ] {
+            cypher.push_layer(
+                Layer::new(Padding, Char, SimplePermutation::trivial(3)).with_pad_value(pad_value),
+            );
+        }
+        cypher.push(Stealing, Byte, SimplePermutation::trivial(3));
+
+        let mut buf = BufWriter::new(Vec::new());
+        Serializer::new(&mut buf)
+            .compact(true)
+            .write(&cypher)
+            .unwrap();
+        let written = String::from_utf8(buf.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            written,
+            "5 padding char ranked 3.0 padding-fixed 88 char ranked 3.0 \
+             padding-random char ranked 3.0 padding-frequency char ranked 3.0 \
+             stealing byte ranked 3.0 "
+        );
+        assert_eq!(
+            Deserializer::new(written.as_bytes()).read().unwrap(),
+            cypher
+        );
     }
 }
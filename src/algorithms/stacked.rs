@@ -1,6 +1,12 @@
 use crate::{
-    algorithms::cyphers::{PadDecrypt, PadEncrypt, UnpadDecrypt, UnpadEncrypt},
-    datastructs::{BitVector, ProvidesPad},
+    algorithms::cyphers::{
+        Blocky, IndexEncrypt, PadDecrypt, PadEncrypt, StealDecrypt, StealEncrypt, UnpadDecrypt,
+        UnpadEncrypt,
+    },
+    datastructs::{
+        groups_from_str, groups_with_lengths, split_groups, string_from_groups, BitFieldVector,
+        BitVector, CharGroup, Filler, PadElement, ProvidesPad,
+    },
 };
 
 use std::{error::Error, fmt::Debug};
@@ -36,11 +42,36 @@ impl From<VerticalPermutation> for Algorithm {
 }
 
 impl Algorithm {
-    pub fn epad<T: Clone + ProvidesPad>(&self, data: &[T]) -> (usize, Vec<T>) {
+    pub fn block_size(&self) -> usize {
         match self {
-            Algorithm::Permutation(p) => p.encrypt_with_pad(data),
-            Algorithm::RailFence(p) => p.encrypt_with_pad(data),
-            Algorithm::Vertical(p) => p.encrypt_with_pad(data),
+            Algorithm::Permutation(p) => p.get_block_size(),
+            Algorithm::RailFence(p) => p.get_block_size(),
+            Algorithm::Vertical(p) => p.get_block_size(),
+        }
+    }
+
+    ///movement of items inside one block as a simple permutation
+    pub fn as_permutation(&self) -> SimplePermutation {
+        let indices = match self {
+            Algorithm::Permutation(p) => p.encrypt_indices(),
+            Algorithm::RailFence(p) => p.encrypt_indices(),
+            Algorithm::Vertical(p) => p.encrypt_indices(),
+        };
+        //all algorithms move items by valid permutations
+        SimplePermutation::try_from(indices).unwrap()
+    }
+
+    ///algorithm that moves items back. Rail fence and vertical permutations
+    ///have no inverse of their own kind, so they become simple permutations
+    pub fn inverse(&self) -> Algorithm {
+        self.as_permutation().invert().into()
+    }
+
+    pub fn epad<T: Clone>(&self, data: &[T], filler: impl FnMut(&T) -> T) -> (usize, Vec<T>) {
+        match self {
+            Algorithm::Permutation(p) => p.encrypt_with_filler(data, filler),
+            Algorithm::RailFence(p) => p.encrypt_with_filler(data, filler),
+            Algorithm::Vertical(p) => p.encrypt_with_filler(data, filler),
         }
     }
     pub fn dpad<T: Clone + ProvidesPad>(
@@ -69,25 +100,109 @@ impl Algorithm {
             Algorithm::Vertical(p) => p.decrypt_unpad(data),
         }
     }
+
+    pub fn esteal<T: Clone>(&self, data: &[T]) -> Vec<T> {
+        match self {
+            Algorithm::Permutation(p) => p.encrypt_stealing(data),
+            Algorithm::RailFence(p) => p.encrypt_stealing(data),
+            Algorithm::Vertical(p) => p.encrypt_stealing(data),
+        }
+    }
+    pub fn dsteal<T: Clone + ProvidesPad>(&self, data: &[T]) -> Vec<T> {
+        match self {
+            Algorithm::Permutation(p) => p.decrypt_stealing(data),
+            Algorithm::RailFence(p) => p.decrypt_stealing(data),
+            Algorithm::Vertical(p) => p.decrypt_stealing(data),
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum PadApproach {
     Padding,
     Unpadding,
+    ///keeps the length like `Unpadding`, but the last incomplete block is
+    ///permuted together with the end of the previous one
+    Stealing,
+}
+
+///values that fill the last block
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PadValue {
+    ///`\0` characters, zero bytes and bits, empty lines
+    #[default]
+    Default,
+    ///character or number given by user
+    Fixed(u32),
+    ///uniformly random element of those present in the message
+    Random,
+    ///random element of the message, frequent elements are chosen more often
+    Frequency,
+    ///pad that can be removed without knowing the original length, so that
+    ///the layer can be decrypted without sizes. Data that fills whole blocks
+    ///gets one more block of pad
+    Delimited,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum EncryptionStyle {
     Bit,
+    ///units of the given number of bits, from 1 to 32
+    Bits(usize),
     Byte,
     Char,
-    Group(usize),
+    Group(usize, GroupRemainder),
+    Word,
+    Line,
+    Grapheme,
+}
+
+///what to do with characters that do not fill the last group
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GroupRemainder {
+    ///text must be divisible into groups
+    Reject,
+    ///last group is filled up with the character, size record keeps the
+    ///number of characters to cut it off
+    Pad(char),
+    ///short last group is not permuted and stays at the end
+    Bypass,
+    ///short last group is permuted like any other group, size record keeps
+    ///the number of characters to find out its length
+    Record,
+}
+
+///one step of a stacked cypher
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layer {
+    pub pad_approach: PadApproach,
+    ///what fills the last block, used only with `PadApproach::Padding`
+    pub pad_value: PadValue,
+    pub style: EncryptionStyle,
+    pub algorithm: Algorithm,
+}
+
+impl Layer {
+    pub fn new<C>(pad_approach: PadApproach, style: EncryptionStyle, cypher: C) -> Self
+    where
+        Algorithm: From<C>,
+    {
+        Layer {
+            pad_approach,
+            pad_value: PadValue::Default,
+            style,
+            algorithm: cypher.into(),
+        }
+    }
+
+    pub fn with_pad_value(self, pad_value: PadValue) -> Self {
+        Layer { pad_value, ..self }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct StackedCypher {
-    algorithms: Vec<(PadApproach, EncryptionStyle, Algorithm)>,
+    algorithms: Vec<Layer>,
 }
 
 impl StackedCypher {
@@ -99,104 +214,821 @@ impl StackedCypher {
     where
         Algorithm: From<C>,
     {
-        self.algorithms.push((pad_approach, style, cypher.into()))
+        self.algorithms
+            .push(Layer::new(pad_approach, style, cypher))
     }
 
-    fn e_with_padding<T: ProvidesPad + Clone>(
-        data: &[T],
-        op: &Algorithm,
-        pad_approach: PadApproach,
-    ) -> (usize, Vec<T>) {
-        match pad_approach {
-            PadApproach::Padding => op.epad(data),
-            PadApproach::Unpadding => (data.len(), op.eunpad(data)),
+    pub fn push_layer(&mut self, layer: Layer) {
+        self.algorithms.push(layer)
+    }
+
+    fn check_layer(&self, index: usize) -> Result<(), Box<dyn Error>> {
+        if index >= self.algorithms.len() {
+            return Err(format!(
+                "no layer {index}, the key has {} layer(s) numbered from 0",
+                self.algorithms.len()
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    ///`index` can be the number of layers to add one on top
+    pub fn insert_layer(&mut self, index: usize, layer: Layer) -> Result<(), Box<dyn Error>> {
+        if index != self.algorithms.len() {
+            self.check_layer(index)?;
+        }
+        self.algorithms.insert(index, layer);
+        Ok(())
+    }
+
+    pub fn remove_layer(&mut self, index: usize) -> Result<Layer, Box<dyn Error>> {
+        self.check_layer(index)?;
+        if self.algorithms.len() == 1 {
+            return Err("cannot remove the only layer of the key".into());
+        }
+        Ok(self.algorithms.remove(index))
+    }
+
+    pub fn replace_layer(&mut self, index: usize, layer: Layer) -> Result<Layer, Box<dyn Error>> {
+        self.check_layer(index)?;
+        Ok(std::mem::replace(&mut self.algorithms[index], layer))
+    }
+
+    pub fn swap_layers(&mut self, first: usize, second: usize) -> Result<(), Box<dyn Error>> {
+        self.check_layer(first)?;
+        self.check_layer(second)?;
+        if first == second {
+            return Err(format!("layer {first} cannot be swapped with itself").into());
         }
+        self.algorithms.swap(first, second);
+        Ok(())
     }
 
-    fn d_with_padding<T: ProvidesPad + Clone>(
+    fn e_with_padding<T: PadElement>(
         data: &[T],
-        size: usize,
-        op: &Algorithm,
-        pad_approach: PadApproach,
+        layer: &Layer,
+    ) -> Result<(usize, Vec<T>), Box<dyn Error>> {
+        Ok(match (layer.pad_approach, layer.pad_value) {
+            (PadApproach::Padding, PadValue::Delimited) => {
+                let Some(reference) = data.last() else {
+                    return Ok((0, vec![]));
+                };
+                let unit = Self::delimiting_unit::<T>(layer);
+                let mut padded = data.to_vec();
+                padded.extend(T::delimiting_pad(reference, unit - data.len() % unit, unit));
+                let (_, encrypted) = layer.algorithm.epad(&padded, T::get_pad_value);
+                (data.len(), encrypted)
+            }
+            (PadApproach::Padding, _) => {
+                let filler = Filler::new(layer.pad_value, data)?;
+                layer
+                    .algorithm
+                    .epad(data, |reference| filler.fill(reference))
+            }
+            (PadApproach::Unpadding, _) => (data.len(), layer.algorithm.eunpad(data)),
+            (PadApproach::Stealing, _) => (data.len(), layer.algorithm.esteal(data)),
+        })
+    }
+
+    ///`size` is needed only for layers that do not delimit their pad
+    fn d_with_padding<T: PadElement>(
+        data: &[T],
+        size: Option<usize>,
+        layer: &Layer,
     ) -> Result<Vec<T>, Box<dyn Error>> {
-        match pad_approach {
-            PadApproach::Padding => op.dpad(data, size),
-            PadApproach::Unpadding => Ok(op.dunpad(data)),
+        match (layer.pad_approach, layer.pad_value) {
+            (PadApproach::Padding, PadValue::Delimited) => {
+                let mut decrypted = layer.algorithm.dpad(data, data.len())?;
+                T::strip_delimiting_pad(&mut decrypted, Self::delimiting_unit::<T>(layer))?;
+                Ok(decrypted)
+            }
+            (PadApproach::Padding, _) => layer.algorithm.dpad(data, Self::need_size(size)?),
+            (PadApproach::Unpadding, _) => Ok(layer.algorithm.dunpad(data)),
+            (PadApproach::Stealing, _) => Ok(layer.algorithm.dsteal(data)),
+        }
+    }
+
+    ///delimited pad ends on a block that is also a whole storage unit
+    fn delimiting_unit<T: PadElement>(layer: &Layer) -> usize {
+        let block_size = layer.algorithm.block_size();
+        let unit = T::storage_unit();
+        let (mut a, mut b) = (block_size, unit);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        block_size / a * unit
+    }
+
+    fn need_size(size: Option<usize>) -> Result<usize, Box<dyn Error>> {
+        size.ok_or_else(|| "sizes are needed to decrypt this message".into())
+    }
+
+    ///number of elements produced from `len` elements
+    fn encrypted_len(len: usize, layer: &Layer) -> usize {
+        let block_size = layer.algorithm.block_size();
+        match (layer.pad_approach, layer.pad_value) {
+            (PadApproach::Padding, PadValue::Delimited) if len == 0 => 0,
+            (PadApproach::Padding, PadValue::Delimited) => (len / block_size + 1) * block_size,
+            (PadApproach::Padding, _) => len.div_ceil(block_size) * block_size,
+            (PadApproach::Unpadding | PadApproach::Stealing, _) => len,
+        }
+    }
+
+    ///whether decryption needs sizes returned by `encrypt`. Layers with
+    ///`PadValue::Delimited` and unpadded layers find out lengths from the data
+    ///itself, unless their elements record lengths in sizes
+    pub fn requires_sizes(&self) -> bool {
+        self.algorithms.iter().any(|layer| {
+            let records_length = matches!(
+                layer.style,
+                EncryptionStyle::Bits(_)
+                    | EncryptionStyle::Group(_, GroupRemainder::Pad(_) | GroupRemainder::Record)
+            );
+            records_length
+                || (layer.pad_approach == PadApproach::Padding
+                    && layer.pad_value != PadValue::Delimited)
+        })
+    }
+
+    fn e_groups(
+        chars: &[char],
+        group_size: usize,
+        remainder: GroupRemainder,
+        layer: &Layer,
+    ) -> Result<(usize, String), Box<dyn Error>> {
+        Ok(match remainder {
+            GroupRemainder::Reject => {
+                let groups = groups_from_str(&chars.iter().collect::<String>(), group_size)?;
+                let (size, encrypted) = Self::e_with_padding(&groups, layer)?;
+                (size, string_from_groups(&encrypted))
+            }
+            GroupRemainder::Pad(filler) => {
+                let mut padded = chars.to_vec();
+                padded.resize(chars.len().div_ceil(group_size) * group_size, filler);
+                let (groups, _) = split_groups(&padded, group_size);
+                let (_, encrypted) = Self::e_with_padding(&groups, layer)?;
+                (chars.len(), string_from_groups(&encrypted))
+            }
+            GroupRemainder::Bypass => {
+                let (groups, tail) = split_groups(chars, group_size);
+                let (size, encrypted) = Self::e_with_padding(&groups, layer)?;
+                let mut encrypted = string_from_groups(&encrypted);
+                encrypted.extend(tail);
+                (size, encrypted)
+            }
+            GroupRemainder::Record => {
+                let groups = Self::recorded_groups(chars, group_size);
+                let (_, encrypted) = Self::e_with_padding(&groups, layer)?;
+                (chars.len(), string_from_groups(&encrypted))
+            }
+        })
+    }
+
+    fn d_groups(
+        chars: &[char],
+        size: Option<usize>,
+        group_size: usize,
+        remainder: GroupRemainder,
+        layer: &Layer,
+    ) -> Result<String, Box<dyn Error>> {
+        Ok(match remainder {
+            GroupRemainder::Reject => {
+                let groups = groups_from_str(&chars.iter().collect::<String>(), group_size)?;
+                string_from_groups(&Self::d_with_padding(&groups, size, layer)?)
+            }
+            GroupRemainder::Pad(_) => {
+                let size = Self::need_size(size)?;
+                let groups = groups_from_str(&chars.iter().collect::<String>(), group_size)?;
+                let decrypted =
+                    Self::d_with_padding(&groups, Some(size.div_ceil(group_size)), layer)?;
+                string_from_groups(&decrypted).chars().take(size).collect()
+            }
+            GroupRemainder::Bypass => {
+                let (groups, tail) = split_groups(chars, group_size);
+                let mut decrypted =
+                    string_from_groups(&Self::d_with_padding(&groups, size, layer)?);
+                decrypted.extend(tail);
+                decrypted
+            }
+            GroupRemainder::Record => {
+                //encrypting groups of the same lengths shows where the short
+                //group and padding groups went
+                let template =
+                    Self::recorded_groups(&vec!['\0'; Self::need_size(size)?], group_size);
+                let (_, lengths) = Self::e_with_padding(&template, layer)?;
+                let groups = groups_with_lengths(chars, lengths.iter().map(CharGroup::char_count))?;
+                string_from_groups(&Self::d_with_padding(&groups, Some(template.len()), layer)?)
+            }
+        })
+    }
+
+    fn recorded_groups(chars: &[char], group_size: usize) -> Vec<CharGroup> {
+        let (mut groups, tail) = split_groups(chars, group_size);
+        if !tail.is_empty() {
+            groups.push(CharGroup::new(tail.to_vec()));
+        }
+        groups
+    }
+
+    fn check_bit_field_pad(layer: &Layer, width: usize) -> Result<(), Box<dyn Error>> {
+        match layer.pad_value {
+            PadValue::Fixed(code) if (code as u64) >> width != 0 => {
+                Err(format!("pad value {code} does not fit into {width} bits").into())
+            }
+            _ => Ok(()),
         }
     }
 
     pub fn encrypt(&self, data: &[u8]) -> Result<(Vec<usize>, Vec<u8>), Box<dyn Error>> {
-        self.algorithms.iter().try_fold(
-            (vec![], data.to_vec()),
-            |(mut indices, data), (pad_approach, style, op)| {
-                let (created_indices, data) = match style {
+        self.algorithms
+            .iter()
+            .try_fold((vec![], data.to_vec()), |(mut indices, data), layer| {
+                let (created_indices, data) = match layer.style {
                     EncryptionStyle::Bit => {
                         let bits = crate::datastructs::BitVector::from(data.as_slice()).0;
-                        let (size, encrypted) = Self::e_with_padding(&bits, op, *pad_approach);
+                        let (size, encrypted) = Self::e_with_padding(&bits, layer)?;
                         (size, BitVector(encrypted).into())
                     }
-                    EncryptionStyle::Byte => Self::e_with_padding(&data, op, *pad_approach),
+                    EncryptionStyle::Bits(width) => {
+                        Self::check_bit_field_pad(layer, width)?;
+                        let fields = BitFieldVector::from_bytes(&data, width)?;
+                        let (_, encrypted) = Self::e_with_padding(&fields.fields, layer)?;
+                        //length in bytes tells how many units there were and
+                        //how many bits of the last one are real
+                        (
+                            data.len(),
+                            BitFieldVector {
+                                width,
+                                fields: encrypted,
+                            }
+                            .into(),
+                        )
+                    }
+                    EncryptionStyle::Byte => Self::e_with_padding(&data, layer)?,
                     EncryptionStyle::Char => {
                         let chars = String::from_utf8(data)?.chars().collect::<Vec<_>>();
-                        let (size, encrypted) = Self::e_with_padding(&chars, op, *pad_approach);
+                        let (size, encrypted) = Self::e_with_padding(&chars, layer)?;
                         (size, encrypted.into_iter().collect::<String>().into_bytes())
                     }
-                    &EncryptionStyle::Group(group_size) => {
-                        let string = String::from_utf8(data)?;
-                        let groups =
-                            crate::datastructs::groups_from_str(string.as_str(), group_size)?;
-
-                        let (size, encrypted) = Self::e_with_padding(&groups, op, *pad_approach);
+                    EncryptionStyle::Group(group_size, remainder) => {
+                        let chars = String::from_utf8(data)?.chars().collect::<Vec<_>>();
+                        let (size, encrypted) =
+                            Self::e_groups(&chars, group_size, remainder, layer)?;
+                        (size, encrypted.into_bytes())
+                    }
+                    EncryptionStyle::Grapheme => {
+                        let graphemes =
+                            crate::datastructs::graphemes_from_str(&String::from_utf8(data)?);
+                        let (size, encrypted) = Self::e_with_padding(&graphemes, layer)?;
                         (
                             size,
-                            crate::datastructs::string_from_groups(&encrypted).into_bytes(),
+                            crate::datastructs::string_from_graphemes(&encrypted)?.into_bytes(),
+                        )
+                    }
+                    EncryptionStyle::Word => {
+                        let words = crate::datastructs::words_from_str(&String::from_utf8(data)?);
+                        let (size, encrypted) = Self::e_with_padding(&words.tokens, layer)?;
+                        (
+                            size,
+                            String::from(words.with_tokens(encrypted)).into_bytes(),
+                        )
+                    }
+                    EncryptionStyle::Line => {
+                        let lines = crate::datastructs::lines_from_str(&String::from_utf8(data)?);
+                        let (size, encrypted) = Self::e_with_padding(&lines.tokens, layer)?;
+                        (
+                            size,
+                            String::from(lines.with_tokens(encrypted)).into_bytes(),
                         )
                     }
                 };
 
                 indices.push(created_indices);
                 Ok((indices, data))
-            },
-        )
+            })
     }
 
     pub fn decrypt(&self, (sizes, data): (Vec<usize>, Vec<u8>)) -> Result<Vec<u8>, Box<dyn Error>> {
-        self.algorithms.iter().zip(sizes.iter()).rev().try_fold(
-            data.to_vec(),
-            |data, ((pad_approach, style, op), &size)| {
-                let data: Vec<u8> = match style {
+        self.decrypt_layers(sizes.into_iter().map(Some).collect(), data)
+    }
+
+    ///decrypts messages of keys that do not need sizes, see `requires_sizes`
+    pub fn decrypt_without_sizes(&self, data: Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.decrypt_layers(vec![None; self.algorithms.len()], data)
+    }
+
+    fn decrypt_layers(
+        &self,
+        sizes: Vec<Option<usize>>,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.algorithms
+            .iter()
+            .zip(sizes.iter())
+            .rev()
+            .try_fold(data, |data, (layer, &size)| {
+                let data: Vec<u8> = match layer.style {
                     EncryptionStyle::Bit => {
                         let bits = crate::datastructs::BitVector::from(data.as_slice()).0;
-                        let encrypted = Self::d_with_padding(&bits, size, op, *pad_approach)?;
+                        let encrypted = Self::d_with_padding(&bits, size, layer)?;
                         BitVector(encrypted).into()
                     }
-                    EncryptionStyle::Byte => Self::d_with_padding(&data, size, op, *pad_approach)?,
+                    EncryptionStyle::Bits(width) => {
+                        let size = Self::need_size(size)?;
+                        let units = BitFieldVector::units_for(size, width);
+                        let encrypted_units = Self::encrypted_len(units, layer);
+                        if data.len() != (encrypted_units * width).div_ceil(8) {
+                            return Err(format!(
+                                "expected {} bytes of {width} bit units, got {}",
+                                (encrypted_units * width).div_ceil(8),
+                                data.len()
+                            )
+                            .into());
+                        }
+
+                        let mut fields = BitFieldVector::from_bytes(&data, width)?;
+                        fields.fields.truncate(encrypted_units);
+                        let decrypted = Self::d_with_padding(&fields.fields, Some(units), layer)?;
+
+                        let mut bytes: Vec<u8> = BitFieldVector {
+                            width,
+                            fields: decrypted,
+                        }
+                        .into();
+                        bytes.truncate(size);
+                        bytes
+                    }
+                    EncryptionStyle::Byte => Self::d_with_padding(&data, size, layer)?,
                     EncryptionStyle::Char => {
                         let chars = String::from_utf8(data)?.chars().collect::<Vec<_>>();
-                        let encrypted = Self::d_with_padding(&chars, size, op, *pad_approach)?;
+                        let encrypted = Self::d_with_padding(&chars, size, layer)?;
                         encrypted.into_iter().collect::<String>().into_bytes()
                     }
-                    &EncryptionStyle::Group(group_size) => {
-                        let string = String::from_utf8(data)?;
-                        let groups =
-                            crate::datastructs::groups_from_str(string.as_str(), group_size)?;
-
-                        let encrypted = Self::d_with_padding(&groups, size, op, *pad_approach)?;
-
-                        crate::datastructs::string_from_groups(&encrypted).into_bytes()
+                    EncryptionStyle::Group(group_size, remainder) => {
+                        let chars = String::from_utf8(data)?.chars().collect::<Vec<_>>();
+                        Self::d_groups(&chars, size, group_size, remainder, layer)?.into_bytes()
+                    }
+                    EncryptionStyle::Grapheme => {
+                        let graphemes =
+                            crate::datastructs::graphemes_from_str(&String::from_utf8(data)?);
+                        let decrypted = Self::d_with_padding(&graphemes, size, layer)?;
+                        crate::datastructs::string_from_graphemes(&decrypted)?.into_bytes()
+                    }
+                    EncryptionStyle::Word => {
+                        let words = crate::datastructs::words_from_str(&String::from_utf8(data)?);
+                        let decrypted = Self::d_with_padding(&words.tokens, size, layer)?;
+                        String::from(words.with_tokens(decrypted)).into_bytes()
+                    }
+                    EncryptionStyle::Line => {
+                        let lines = crate::datastructs::lines_from_str(&String::from_utf8(data)?);
+                        let decrypted = Self::d_with_padding(&lines.tokens, size, layer)?;
+                        String::from(lines.with_tokens(decrypted)).into_bytes()
                     }
                 };
 
                 Ok(data)
-            },
-        )
+            })
+    }
+
+    ///cypher that encrypts what this one decrypts. Padding of the last block
+    ///happens in a different place, so the inverse is exact only for data that
+    ///fills whole blocks of every layer
+    pub fn inverse(&self) -> StackedCypher {
+        StackedCypher {
+            algorithms: self
+                .algorithms
+                .iter()
+                .rev()
+                .map(|layer| Layer {
+                    algorithm: layer.algorithm.inverse(),
+                    ..layer.clone()
+                })
+                .collect(),
+        }
+    }
+
+    ///cypher that applies `self` and then `other`. Neighbouring padded layers
+    ///with the same element and block size are merged into one permutation,
+    ///which gives exactly the same output
+    pub fn then(&self, other: &StackedCypher) -> StackedCypher {
+        let mut algorithms: Vec<Layer> = vec![];
+
+        for layer in self.algorithms.iter().chain(other.algorithms.iter()) {
+            if let Some(last) = algorithms.last_mut() {
+                if layer.pad_approach == PadApproach::Padding
+                    && last.pad_approach == PadApproach::Padding
+                    && layer.pad_value != PadValue::Delimited
+                    && layer.style == last.style
+                    && layer.algorithm.block_size() == last.algorithm.block_size()
+                {
+                    //block sizes are equal, so composition cannot fail. Data
+                    //of the second layer fills whole blocks, so only pad
+                    //value of the first one matters. Delimited pad adds a
+                    //block even then, so such layers are kept apart
+                    last.algorithm = last
+                        .algorithm
+                        .as_permutation()
+                        .compose(&layer.algorithm.as_permutation())
+                        .unwrap()
+                        .into();
+                    continue;
+                }
+            }
+            algorithms.push(layer.clone());
+        }
+
+        StackedCypher { algorithms }
+    }
+
+    ///layers of `self` followed by layers of `other`, kept as they are
+    pub fn concat(&self, other: &StackedCypher) -> StackedCypher {
+        StackedCypher {
+            algorithms: self
+                .algorithms
+                .iter()
+                .chain(&other.algorithms)
+                .cloned()
+                .collect(),
+        }
     }
 
     pub(crate) fn len(&self) -> usize {
         self.algorithms.len()
     }
 
-    pub(crate) fn items(&self) -> impl Iterator<Item = &(PadApproach, EncryptionStyle, Algorithm)> {
+    pub(crate) fn items(&self) -> impl Iterator<Item = &Layer> {
         self.algorithms.iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::algorithms::{
+        permutation::SimplePermutation, rail_fence::RailFenceCypher, stacked::StackedCypher,
+        vertical::VerticalPermutation,
+    };
+
+    use crate::algorithms::stacked::{EncryptionStyle::*, PadApproach::*};
+
+    fn get_cypher() -> StackedCypher {
+        let mut cypher = StackedCypher::new();
+        cypher.push(
+            Padding,
+            Byte,
+            SimplePermutation::try_from(vec![3, 1, 0, 2]).unwrap(),
+        );
+        cypher.push(Unpadding, Byte, RailFenceCypher::try_new(3, 8).unwrap());
+        cypher.push(
+            Padding,
+            Bit,
+            VerticalPermutation::try_new(
+                2,
+                4,
+                SimplePermutation::try_from(vec![2, 0, 3, 1]).unwrap(),
+            )
+            .unwrap(),
+        );
+        cypher
+    }
+
+    #[test]
+    fn inverse_encrypts_like_decryption() {
+        let cypher = get_cypher();
+        let inverse = cypher.inverse();
+
+        let data = (b'a'..b'a' + 24).collect::<Vec<_>>();
+
+        let (sizes, encrypted) = cypher.encrypt(&data).unwrap();
+        assert_eq!(inverse.encrypt(&encrypted).unwrap().1, data);
+        assert_eq!(
+            cypher.decrypt((sizes, data.clone())).unwrap(),
+            inverse.encrypt(&data).unwrap().1
+        );
+    }
+
+    #[test]
+    fn then_merges_padded_layers() {
+        let cypher = get_cypher();
+        let mut other = StackedCypher::new();
+        other.push(Padding, Bit, RailFenceCypher::try_new(3, 8).unwrap());
+        other.push(
+            Padding,
+            Bit,
+            SimplePermutation::try_from(vec![7, 6, 5, 4, 3, 2, 1, 0]).unwrap(),
+        );
+
+        let composed = cypher.then(&other);
+        assert_eq!(composed.len(), 3);
+        assert_eq!(cypher.concat(&other).len(), 5);
+
+        let data = "frogs are green".as_bytes();
+        let (_, expected) = other.encrypt(&cypher.encrypt(data).unwrap().1).unwrap();
+        let (sizes, produced) = composed.encrypt(data).unwrap();
+        assert_eq!(produced, expected);
+        assert_eq!(composed.decrypt((sizes, produced)).unwrap(), data);
+    }
+
+    #[test]
+    fn concat_applies_keys_in_order() {
+        let cypher = get_cypher();
+        let mut other = StackedCypher::new();
+        other.push(
+            Padding,
+            Byte,
+            SimplePermutation::try_from(vec![1, 2, 0]).unwrap(),
+        );
+        other.push(Padding, Byte, RailFenceCypher::try_new(2, 5).unwrap());
+
+        let concatenated = cypher.concat(&other);
+        assert!(concatenated.items().eq(cypher.items().chain(other.items())));
+
+        let data = "frogs are green".as_bytes();
+        let (first_sizes, first) = cypher.encrypt(data).unwrap();
+        let (second_sizes, expected) = other.encrypt(&first).unwrap();
+        let (sizes, produced) = concatenated.encrypt(data).unwrap();
+        assert_eq!(produced, expected);
+        assert_eq!(sizes, [first_sizes, second_sizes].concat());
+        assert_eq!(concatenated.decrypt((sizes, produced)).unwrap(), data);
+    }
+
+    #[test]
+    fn edits_layers() {
+        let mut cypher = get_cypher();
+        let mut layers = cypher.items().cloned().collect::<Vec<_>>();
+
+        cypher.swap_layers(0, 2).unwrap();
+        layers.swap(0, 2);
+        assert!(cypher.items().eq(layers.iter()));
+
+        let removed = cypher.remove_layer(1).unwrap();
+        cypher.insert_layer(2, removed.clone()).unwrap();
+        assert_eq!(cypher.items().last(), Some(&removed));
+        assert_eq!(cypher.replace_layer(2, layers[0].clone()).unwrap(), removed);
+
+        assert!(cypher.insert_layer(4, removed).is_err());
+        assert!(cypher.swap_layers(0, 3).is_err());
+        assert!(cypher.swap_layers(1, 1).is_err());
+        cypher.remove_layer(0).unwrap();
+        cypher.remove_layer(0).unwrap();
+        assert!(cypher.remove_layer(0).is_err());
+    }
+
+    #[test]
+    fn words_and_lines_round_trip() {
+        let mut cypher = StackedCypher::new();
+        cypher.push(
+            Padding,
+            Word,
+            SimplePermutation::try_from(vec![2, 0, 1]).unwrap(),
+        );
+        cypher.push(
+            Unpadding,
+            Line,
+            SimplePermutation::try_from(vec![1, 0]).unwrap(),
+        );
+
+        let text = "first line, with words\nsecond one\nthird\n— and the tail";
+        let (sizes, encrypted) = cypher.encrypt(text.as_bytes()).unwrap();
+        assert_ne!(encrypted, text.as_bytes());
+        assert_eq!(cypher.decrypt((sizes, encrypted)).unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn graphemes_round_trip() {
+        let mut cypher = StackedCypher::new();
+        cypher.push(
+            Padding,
+            Grapheme,
+            SimplePermutation::try_from(vec![3, 0, 4, 1, 2]).unwrap(),
+        );
+
+        let text = "Ёжик e\u{301}te\u{301} 🇷🇺🇺🇸 👨‍👩‍👧 한국어 क्षि";
+        let (sizes, encrypted) = cypher.encrypt(text.as_bytes()).unwrap();
+        let encrypted_text = String::from_utf8(encrypted.clone()).unwrap();
+        assert!(encrypted_text.contains("e\u{301}"));
+        assert!(encrypted_text.contains("👨‍👩‍👧"));
+        assert_eq!(cypher.decrypt((sizes, encrypted)).unwrap(), text.as_bytes());
+
+        assert!(cypher.encrypt("\u{301}abcd".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn bit_fields_round_trip() {
+        let mut cypher = StackedCypher::new();
+        cypher.push(
+            Padding,
+            Bits(6),
+            SimplePermutation::try_from(vec![3, 0, 4, 1, 2]).unwrap(),
+        );
+        cypher.push(Unpadding, Bits(12), RailFenceCypher::try_new(2, 5).unwrap());
+        cypher.push(
+            Padding,
+            Bits(3),
+            SimplePermutation::try_from(vec![1, 0]).unwrap(),
+        );
+
+        for length in [0, 1, 2, 7, 16, 33] {
+            let data = (0..length).map(|i| (i * 37 + 11) as u8).collect::<Vec<_>>();
+            let (sizes, encrypted) = cypher.encrypt(&data).unwrap();
+            assert_eq!(cypher.decrypt((sizes, encrypted)).unwrap(), data);
+        }
+
+        let mut nibbles = StackedCypher::new();
+        nibbles.push(
+            Unpadding,
+            Bits(4),
+            SimplePermutation::try_from(vec![1, 0]).unwrap(),
+        );
+        assert_eq!(nibbles.encrypt(b"\x12\x34").unwrap().1, b"\x21\x43");
+    }
+
+    #[test]
+    fn stealing_moves_the_tail_with_full_blocks() {
+        let mut cypher = StackedCypher::new();
+        cypher.push(
+            Stealing,
+            Byte,
+            SimplePermutation::try_from(vec![3, 0, 1, 2]).unwrap(),
+        );
+        //"abcd" becomes "bcda", then the last four "daef" become "aefd"
+        assert_eq!(cypher.encrypt(b"abcdef").unwrap().1, b"bcaefd".to_vec());
+
+        cypher.push(Stealing, Char, RailFenceCypher::try_new(2, 5).unwrap());
+        cypher.push(
+            Stealing,
+            Bit,
+            SimplePermutation::try_from(vec![2, 4, 0, 1, 3]).unwrap(),
+        );
+        for length in 0..20 {
+            let data = (b'a'..).take(length).collect::<Vec<_>>();
+            let (sizes, encrypted) = cypher.encrypt(&data).unwrap();
+            assert_eq!(encrypted.len(), data.len());
+            assert_eq!(cypher.decrypt_without_sizes(encrypted).unwrap(), data);
+            assert_eq!(
+                cypher
+                    .decrypt((sizes, cypher.encrypt(&data).unwrap().1))
+                    .unwrap(),
+                data
+            );
+        }
+        assert!(!cypher.requires_sizes());
+    }
+
+    #[test]
+    fn group_remainders_round_trip() {
+        use crate::algorithms::stacked::GroupRemainder;
+
+        let text = "шифруем группы по три";
+        for remainder in [
+            GroupRemainder::Pad('x'),
+            GroupRemainder::Bypass,
+            GroupRemainder::Record,
+        ] {
+            for pad in [Padding, Unpadding, Stealing] {
+                let mut cypher = StackedCypher::new();
+                cypher.push(
+                    pad,
+                    Group(3, remainder),
+                    SimplePermutation::try_from(vec![2, 0, 3, 1]).unwrap(),
+                );
+                cypher.push(
+                    pad,
+                    Group(2, remainder),
+                    RailFenceCypher::try_new(2, 3).unwrap(),
+                );
+
+                for length in [0, 1, 5, 12, 20] {
+                    let data = text.chars().take(length).collect::<String>();
+                    let (sizes, encrypted) = cypher.encrypt(data.as_bytes()).unwrap();
+                    assert_eq!(
+                        cypher.decrypt((sizes, encrypted)).unwrap(),
+                        data.as_bytes(),
+                        "{remainder:?} {pad:?} {length}"
+                    );
+                }
+            }
+        }
+
+        let mut bypass = StackedCypher::new();
+        bypass.push(
+            Unpadding,
+            Group(2, GroupRemainder::Bypass),
+            SimplePermutation::try_from(vec![1, 0]).unwrap(),
+        );
+        assert_eq!(bypass.encrypt(b"abcde").unwrap().1, b"cdabe");
+
+        let mut reject = StackedCypher::new();
+        reject.push(
+            Padding,
+            Group(2, GroupRemainder::Reject),
+            SimplePermutation::try_from(vec![1, 0]).unwrap(),
+        );
+        assert!(reject.encrypt(b"abcde").is_err());
+    }
+
+    #[test]
+    fn pad_values() {
+        use crate::algorithms::stacked::{Layer, PadValue};
+
+        let permutation = SimplePermutation::try_from(vec![4, 0, 3, 1, 2]).unwrap();
+        let cypher_with = |pad_value, style| {
+            let mut cypher = StackedCypher::new();
+            cypher.push_layer(
+                Layer::new(Padding, style, permutation.clone()).with_pad_value(pad_value),
+            );
+            cypher
+        };
+
+        let fixed = cypher_with(PadValue::Fixed('X' as u32), Char);
+        let (sizes, encrypted) = fixed.encrypt(b"abcdefg").unwrap();
+        assert_eq!(encrypted.iter().filter(|&&c| c == b'X').count(), 3);
+        assert_eq!(fixed.decrypt((sizes, encrypted)).unwrap(), b"abcdefg");
+
+        for pad_value in [PadValue::Random, PadValue::Frequency] {
+            for style in [
+                Char,
+                Word,
+                Group(2, crate::algorithms::stacked::GroupRemainder::Record),
+            ] {
+                let cypher = cypher_with(pad_value, style);
+                let text = "aab aab aab aab aab aab b";
+                let (sizes, encrypted) = cypher.encrypt(text.as_bytes()).unwrap();
+                let encrypted_text = String::from_utf8(encrypted.clone()).unwrap();
+                assert!(encrypted_text.chars().all(|c| "ab ".contains(c)));
+                assert_eq!(cypher.decrypt((sizes, encrypted)).unwrap(), text.as_bytes());
+            }
+        }
+
+        assert!(cypher_with(PadValue::Fixed(300), Byte)
+            .encrypt(b"abc")
+            .is_err());
+        assert!(cypher_with(PadValue::Fixed(16), Bits(4))
+            .encrypt(b"abc")
+            .is_err());
+        assert!(cypher_with(PadValue::Fixed(' ' as u32), Word)
+            .encrypt(b"abc ")
+            .is_err());
+    }
+
+    #[test]
+    fn delimited_padding_needs_no_sizes() {
+        use crate::algorithms::stacked::{GroupRemainder, Layer, PadValue};
+
+        let permutation = SimplePermutation::try_from(vec![2, 0, 3, 1]).unwrap();
+        let delimited = |style| {
+            Layer::new(Padding, style, permutation.clone()).with_pad_value(PadValue::Delimited)
+        };
+
+        let mut cypher = StackedCypher::new();
+        for style in [
+            Char,
+            Word,
+            Line,
+            Grapheme,
+            Group(2, GroupRemainder::Bypass),
+            Byte,
+            Bit,
+        ] {
+            cypher.push_layer(delimited(style));
+        }
+        cypher.push(Unpadding, Byte, RailFenceCypher::try_new(2, 5).unwrap());
+        assert!(!cypher.requires_sizes());
+
+        for text in ["", "a", "abcd", "some words\nand lines\n", "\u{3}\0\0"] {
+            let (_, encrypted) = cypher.encrypt(text.as_bytes()).unwrap();
+            assert_eq!(
+                cypher.decrypt_without_sizes(encrypted).unwrap(),
+                text.as_bytes()
+            );
+        }
+
+        let mut bytes = StackedCypher::new();
+        bytes.push_layer(delimited(Byte));
+        let (_, encrypted) = bytes.encrypt(b"abcdef").unwrap();
+        let mut sorted = encrypted.clone();
+        sorted.sort();
+        assert_eq!(sorted, b"\x02\x02abcdef");
+        let (_, full_block) = bytes.encrypt(b"abcd").unwrap();
+        assert_eq!(full_block.len(), 8);
+        assert!(bytes.decrypt_without_sizes(b"abcd".to_vec()).is_err());
+
+        //blocks of 3 bits end together with a byte only every 24 bits
+        let mut bits = StackedCypher::new();
+        bits.push_layer(
+            Layer::new(Padding, Bit, RailFenceCypher::try_new(2, 3).unwrap())
+                .with_pad_value(PadValue::Delimited),
+        );
+        let (_, encrypted) = bits.encrypt(b"ab").unwrap();
+        assert_eq!(encrypted.len(), 3);
+        assert_eq!(bits.decrypt_without_sizes(encrypted).unwrap(), b"ab");
+
+        let mut sized = StackedCypher::new();
+        sized.push_layer(delimited(Bits(4)));
+        assert!(sized.requires_sizes());
+        sized.push(Padding, Char, permutation.clone());
+        let (sizes, encrypted) = sized.encrypt(b"abc").unwrap();
+        assert_eq!(sized.decrypt((sizes, encrypted.clone())).unwrap(), b"abc");
+        assert!(sized.decrypt_without_sizes(encrypted).is_err());
+    }
+}
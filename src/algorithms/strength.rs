@@ -0,0 +1,345 @@
+use std::fmt::Display;
+
+use super::{
+    permutation::SimplePermutation,
+    stacked::{Algorithm, StackedCypher},
+};
+
+///layers with fewer bits of keyspace than this can be brute forced in an instant
+const SMALL_LAYER_BITS: f64 = 16.0;
+
+///whole keys below these thresholds can be searched exhaustively
+const BROKEN_TOTAL_BITS: f64 = 20.0;
+const WEAK_TOTAL_BITS: f64 = 40.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WarningLevel {
+    None,
+    Low,
+    High,
+    Critical,
+}
+
+impl Display for WarningLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                WarningLevel::None => "none",
+                WarningLevel::Low => "low",
+                WarningLevel::High => "high",
+                WarningLevel::Critical => "critical",
+            }
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LayerStrength {
+    pub block_size: usize,
+    ///bits needed to enumerate all keys of this kind and block size
+    pub keyspace_bits: f64,
+    pub fixed_points: usize,
+    ///`None` if it does not fit into u128
+    pub order: Option<u128>,
+    pub level: WarningLevel,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct StrengthReport {
+    pub layers: Vec<LayerStrength>,
+    ///pairs of neighbouring layers that undo each other
+    pub cancelling: Vec<(usize, usize)>,
+    ///keyspace of layers that actually change the data
+    pub effective_bits: f64,
+    pub level: WarningLevel,
+    pub warnings: Vec<String>,
+}
+
+pub fn analyze_layer(algorithm: &Algorithm) -> LayerStrength {
+    let permutation = algorithm.as_permutation();
+    let block_size = permutation.indices.len();
+    let fixed_points = permutation.fixed_points().len();
+    let order = permutation.order();
+
+    let mut level = WarningLevel::None;
+    let mut warnings = vec![];
+    let mut warn = |warning_level: WarningLevel, message: String| {
+        level = level.max(warning_level);
+        warnings.push(message);
+    };
+
+    let keyspace_bits = match algorithm {
+        Algorithm::Permutation(_) => log2_factorial(block_size),
+        //for a known block size only the number of rows is secret
+        Algorithm::RailFence(r) => ((r.get_inner().columns - 1) as f64).log2(),
+        //block size may be split into rows and columns in several ways
+        Algorithm::Vertical(v) => {
+            let v = v.get_inner();
+            log2_factorial(v.columns) + (divisors(v.rows * v.columns) as f64).log2()
+        }
+    };
+
+    if permutation.is_identity() {
+        warn(
+            WarningLevel::Critical,
+            "layer does not move anything".to_string(),
+        );
+    } else {
+        if fixed_points * 2 > block_size {
+            warn(
+                WarningLevel::High,
+                format!("{fixed_points} of {block_size} items stay in place"),
+            );
+        }
+
+        if block_size >= 4 && order.is_some_and(|o| o <= 2) {
+            warn(
+                WarningLevel::High,
+                "encrypting twice gives back the original data".to_string(),
+            );
+        }
+    }
+
+    if let Algorithm::RailFence(r) = algorithm {
+        let r = r.get_inner();
+        if r.rows + 1 >= r.columns {
+            warn(
+                WarningLevel::High,
+                "rail fence with rows = columns - 1 swaps only two items".to_string(),
+            );
+        }
+    }
+
+    if keyspace_bits < SMALL_LAYER_BITS {
+        warn(
+            WarningLevel::Low,
+            format!("small keyspace of {keyspace_bits:.1} bits"),
+        );
+    }
+
+    LayerStrength {
+        block_size,
+        keyspace_bits,
+        fixed_points,
+        order,
+        level,
+        warnings,
+    }
+}
+
+pub fn analyze(cypher: &StackedCypher) -> StrengthReport {
+    let items = cypher.items().collect::<Vec<_>>();
+    let layers = items
+        .iter()
+        .map(|layer| analyze_layer(&layer.algorithm))
+        .collect::<Vec<_>>();
+
+    let cancelling = items
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| {
+            let (first, second) = (pair[0], pair[1]);
+            first.pad_approach == second.pad_approach
+                && first.style == second.style
+                && first
+                    .algorithm
+                    .as_permutation()
+                    .compose(&second.algorithm.as_permutation())
+                    .is_ok_and(|p| p.is_identity())
+        })
+        .map(|(i, _)| (i, i + 1))
+        .collect::<Vec<_>>();
+
+    let effective_bits = layers
+        .iter()
+        .enumerate()
+        .filter(|(i, layer)| {
+            layer.level < WarningLevel::Critical
+                && !cancelling.iter().any(|&(a, b)| a == *i || b == *i)
+        })
+        .map(|(_, layer)| layer.keyspace_bits)
+        .sum::<f64>();
+
+    let mut level = layers
+        .iter()
+        .map(|l| l.level)
+        .max()
+        .unwrap_or(WarningLevel::Critical);
+    let mut warnings = vec![];
+
+    for (a, b) in &cancelling {
+        level = WarningLevel::Critical;
+        warnings.push(format!("layers {a} and {b} cancel each other"));
+    }
+
+    if effective_bits < BROKEN_TOTAL_BITS {
+        level = level.max(WarningLevel::High);
+        warnings.push(format!(
+            "effective keyspace of {effective_bits:.1} bits can be searched exhaustively"
+        ));
+    } else if effective_bits < WEAK_TOTAL_BITS {
+        level = level.max(WarningLevel::Low);
+        warnings.push(format!(
+            "effective keyspace of {effective_bits:.1} bits is small"
+        ));
+    }
+
+    StrengthReport {
+        layers,
+        cancelling,
+        effective_bits,
+        level,
+        warnings,
+    }
+}
+
+///layer is weak if it leaks most of the data, can be undone trivially or, as
+///a key of its own, searched exhaustively. Same as the level of [`analyze`]
+pub fn is_weak(algorithm: &Algorithm) -> bool {
+    let layer = analyze_layer(algorithm);
+    layer.level >= WarningLevel::High || layer.keyspace_bits < BROKEN_TOTAL_BITS
+}
+
+///smaller simple permutations are always weak
+pub fn min_strong_permutation_size() -> usize {
+    (1..)
+        .find(|&size| log2_factorial(size) >= BROKEN_TOTAL_BITS)
+        .unwrap()
+}
+
+pub fn is_weak_permutation(permutation: &SimplePermutation) -> bool {
+    is_weak(&permutation.clone().into())
+}
+
+fn log2_factorial(n: usize) -> f64 {
+    (2..=n).map(|i| (i as f64).log2()).sum()
+}
+
+fn divisors(n: usize) -> usize {
+    (1..=n).filter(|d| n.is_multiple_of(*d)).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::algorithms::{
+        permutation::SimplePermutation,
+        rail_fence::RailFenceCypher,
+        stacked::{Algorithm, StackedCypher},
+        vertical::VerticalPermutation,
+    };
+
+    use crate::algorithms::stacked::{EncryptionStyle::*, PadApproach::*};
+
+    use super::{analyze, is_weak, WarningLevel};
+
+    #[test]
+    fn layer_strength() {
+        let report = {
+            let mut cypher = StackedCypher::new();
+            cypher.push(Padding, Char, SimplePermutation::trivial(8));
+            cypher.push(Padding, Byte, RailFenceCypher::try_new(7, 8).unwrap());
+            cypher.push(
+                Padding,
+                Byte,
+                SimplePermutation::try_from(vec![1, 0, 3, 2]).unwrap(),
+            );
+            cypher.push(
+                Padding,
+                Byte,
+                SimplePermutation::try_from(vec![4, 0, 5, 1, 6, 2, 7, 3]).unwrap(),
+            );
+            analyze(&cypher)
+        };
+
+        assert_eq!(report.layers[0].level, WarningLevel::Critical);
+        assert_eq!(report.layers[0].fixed_points, 8);
+
+        assert_eq!(report.layers[1].level, WarningLevel::High);
+        assert_eq!(report.layers[1].fixed_points, 6);
+
+        assert_eq!(report.layers[2].level, WarningLevel::High);
+        assert_eq!(report.layers[2].order, Some(2));
+
+        assert_eq!(report.layers[3].level, WarningLevel::Low);
+        assert_eq!(report.layers[3].order, Some(6));
+        assert_eq!(report.layers[3].fixed_points, 0);
+        assert!((report.layers[3].keyspace_bits - 40320f64.log2()).abs() < 1e-9);
+
+        assert_eq!(report.level, WarningLevel::Critical);
+    }
+
+    #[test]
+    fn cancelling_layers() {
+        let permutation = SimplePermutation::try_from(vec![2, 4, 0, 1, 3, 6, 5]).unwrap();
+
+        let mut cypher = StackedCypher::new();
+        cypher.push(Padding, Char, permutation.clone());
+        cypher.push(Padding, Char, permutation.invert());
+        cypher.push(
+            Unpadding,
+            Byte,
+            VerticalPermutation::try_new(3, 12, SimplePermutation::trivial(12)).unwrap(),
+        );
+
+        let report = analyze(&cypher);
+        assert_eq!(report.cancelling, vec![(0, 1)]);
+        assert_eq!(report.level, WarningLevel::Critical);
+        assert!(report.effective_bits < report.layers[2].keyspace_bits + 1e-9);
+    }
+
+    #[test]
+    fn weak_detection() {
+        assert!(is_weak(&SimplePermutation::trivial(5).into()));
+        assert!(is_weak(&RailFenceCypher::try_new(9, 10).unwrap().into()));
+        //moves everything, but all 6 keys of its size are tried at once
+        assert!(is_weak(
+            &SimplePermutation::try_from(vec![1, 2, 0]).unwrap().into()
+        ));
+        assert!(!is_weak(
+            &SimplePermutation::try_from(vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 0])
+                .unwrap()
+                .into()
+        ));
+    }
+
+    #[test]
+    fn weak_layers_are_weak_keys() {
+        let algorithms: Vec<Algorithm> = vec![
+            SimplePermutation::trivial(12).into(),
+            SimplePermutation::try_from(vec![1, 2, 0]).unwrap().into(),
+            SimplePermutation::try_from(vec![3, 0, 1, 2, 7, 4, 5, 6, 9, 8])
+                .unwrap()
+                .into(),
+            SimplePermutation::try_from(vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 0])
+                .unwrap()
+                .into(),
+            RailFenceCypher::try_new(3, 16).unwrap().into(),
+            VerticalPermutation::try_new(
+                2,
+                4,
+                SimplePermutation::try_from(vec![1, 3, 0, 2]).unwrap(),
+            )
+            .unwrap()
+            .into(),
+            VerticalPermutation::try_new(
+                3,
+                12,
+                SimplePermutation::try_from((0..12).rev().collect()).unwrap(),
+            )
+            .unwrap()
+            .into(),
+        ];
+        for algorithm in algorithms {
+            let mut cypher = StackedCypher::new();
+            cypher.push(Padding, Char, algorithm.clone());
+            assert_eq!(
+                is_weak(&algorithm),
+                analyze(&cypher).level >= WarningLevel::High,
+                "{algorithm:?}"
+            );
+        }
+    }
+}
@@ -1,87 +1,1402 @@
-use std::io::{prelude::*, BufWriter};
-use std::{collections::HashMap, error::Error, fs::File};
+use std::ffi::OsString;
+use std::fs::TryLockError;
+use std::io::{self, prelude::*, BufWriter, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    fs,
+    fs::File,
+};
+
+use sha2::{Digest, Sha256};
 
 use crate::algorithms::serialization::{Deserializer, Serializer};
 use crate::algorithms::stacked::StackedCypher;
+use crate::journal::{Change, Journal};
+use crate::metadata::{Date, Metadata};
+use crate::protection::{self, PasswordError};
+
+///first line of versioned files, followed by the version
+const HEADER: &str = "permutations database";
+
+///version written by `save`
+const FORMAT_VERSION: usize = 4;
+
+type Migration = fn(&str) -> Result<String, Box<dyn Error>>;
+
+///`MIGRATIONS[i]` turns content of version `i + 1` into version `i + 2`.
+///Files without a header are version 1
+const MIGRATIONS: [Migration; FORMAT_VERSION - 1] =
+    [migrate_unversioned, migrate_to_metadata, migrate_to_history];
+
+///hashed in front of every record
+const CHECKSUM_DOMAIN: &[u8] = b"permutations database record\0";
+
+///key together with what is known about it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub cypher: StackedCypher,
+    pub metadata: Metadata,
+    ///starts with 1, grows every time the key is replaced
+    pub version: usize,
+    ///previous versions, oldest first
+    pub history: Vec<Revision>,
+}
+
+///replaced version of a key, kept to decrypt what was encrypted with it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Revision {
+    pub version: usize,
+    pub cypher: StackedCypher,
+    ///seconds since unix epoch
+    pub retired: u64,
+}
+
+impl Entry {
+    pub fn new(cypher: StackedCypher, metadata: Metadata) -> Self {
+        Entry {
+            cypher,
+            metadata,
+            version: 1,
+            history: vec![],
+        }
+    }
+
+    ///next version of the key, the current one goes to history
+    pub fn replaced_with(&self, cypher: StackedCypher, metadata: Metadata, now: u64) -> Self {
+        let mut history = self.history.clone();
+        history.push(Revision {
+            version: self.version,
+            cypher: self.cypher.clone(),
+            retired: now,
+        });
+        Entry {
+            cypher,
+            metadata,
+            version: self.version + 1,
+            history,
+        }
+    }
+
+    pub fn revision(&self, version: usize) -> Option<&StackedCypher> {
+        if version == self.version {
+            return Some(&self.cypher);
+        }
+        self.history
+            .iter()
+            .find(|revision| revision.version == version)
+            .map(|revision| &revision.cypher)
+    }
+
+    ///versions from the oldest to the current one
+    pub fn versions(&self) -> impl Iterator<Item = (usize, &StackedCypher)> {
+        self.history
+            .iter()
+            .map(|revision| (revision.version, &revision.cypher))
+            .chain([(self.version, &self.cypher)])
+    }
+}
 
 pub struct Database {
-    data: HashMap<String, StackedCypher>,
-    file: std::fs::File,
+    data: HashMap<String, Entry>,
+    path: PathBuf,
+    ///file is encrypted with it when saved
+    password: Option<String>,
+    ///keys as they were on disk when last loaded or saved, base for merges
+    base: HashMap<String, Entry>,
+    ///hash of the file as it was when last loaded or saved
+    disk_digest: Option<[u8; 32]>,
+    ///the file was encrypted with it when last loaded or saved, differs from
+    ///`password` after a change until the next save
+    disk_password: Option<String>,
+    ///keys were read from the backup when last loaded, the file is damaged
+    from_backup: bool,
+    ///advisory lock held while the database is open, released on drop
+    lock: DatabaseLock,
+    journal: Journal,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LockMode {
+    ///nobody else can open the database
+    Exclusive,
+    ///others can open it in shared mode too, changes made by them are
+    ///detected on save
+    Shared,
+}
+
+pub struct SaveOptions {
+    ///store simple permutations as ranks, which is much shorter for big ones
+    pub compact: bool,
+    ///keep the previous version of the file next to it
+    pub backup: bool,
+    ///combine with changes made by others since the database was loaded
+    pub merge: bool,
+}
+
+///where keys were read from
+#[derive(Debug)]
+pub enum Loaded {
+    Main,
+    ///main file could not be read, keys come from the previous generation
+    Backup {
+        error: String,
+    },
 }
 
 impl Database {
+    ///`name@version` gives the version of the key, older ones included
     pub fn get<'e>(&'e self, key_name: &str) -> Option<&'e StackedCypher> {
+        if let Some(entry) = self.data.get(key_name) {
+            return Some(&entry.cypher);
+        }
+        let (name, version) = split_version(key_name)?;
+        self.data.get(name)?.revision(version)
+    }
+
+    pub fn get_entry<'e>(&'e self, key_name: &str) -> Option<&'e Entry> {
         self.data.get(key_name)
     }
 
-    pub fn get_inner(&self) -> &HashMap<String, StackedCypher> {
+    pub fn get_inner(&self) -> &HashMap<String, Entry> {
         &self.data
     }
 
-    pub fn add(&mut self, key: &str, value: StackedCypher) -> Option<StackedCypher> {
-        self.data.insert(key.to_owned(), value)
+    pub fn add(&mut self, key: &str, value: Entry) -> Option<Entry> {
+        let before = self.data.insert(key.to_owned(), value.clone());
+        if before.as_ref() != Some(&value) {
+            self.journal.record(Change {
+                name: key.to_owned(),
+                before: before.clone(),
+                after: Some(value),
+            });
+        }
+        before
     }
 
-    pub fn delete(&mut self, key: &str) -> Option<StackedCypher> {
-        self.data.remove(key)
+    pub fn delete(&mut self, key: &str) -> Option<Entry> {
+        let before = self.data.remove(key);
+        if before.is_some() {
+            self.journal.record(Change {
+                name: key.to_owned(),
+                before: before.clone(),
+                after: None,
+            });
+        }
+        before
     }
 
-    pub fn load_from_file(file: File) -> Result<Database, Box<dyn Error>> {
+    ///moves the key with all its versions, one step to UNDO
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+        if self.data.contains_key(to) {
+            return Err(format!("key \"{to}\" already exists").into());
+        }
+        let entry = self.data.remove(from).ok_or("no such key")?;
+        self.data.insert(to.to_owned(), entry.clone());
+        self.journal.record_step(vec![
+            Change {
+                name: from.to_owned(),
+                before: Some(entry.clone()),
+                after: None,
+            },
+            Change {
+                name: to.to_owned(),
+                before: None,
+                after: Some(entry),
+            },
+        ]);
+        Ok(())
+    }
+
+    ///keeps `keep` newest versions of the key, returns how many were removed
+    pub fn prune(&mut self, key: &str, keep: usize) -> Result<usize, Box<dyn Error>> {
+        if keep == 0 {
+            return Err("at least the current version has to be kept".into());
+        }
+        let mut entry = self.data.get(key).ok_or("no such key")?.clone();
+        let removed = (entry.history.len() + 1).saturating_sub(keep);
+        if removed > 0 {
+            entry.history.drain(..removed);
+            self.add(key, entry);
+        }
+        Ok(removed)
+    }
+
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    pub fn begin(&mut self) -> Result<(), Box<dyn Error>> {
+        self.journal.begin()
+    }
+
+    ///returns number of committed changes
+    pub fn commit(&mut self) -> Result<usize, Box<dyn Error>> {
+        self.journal.commit()
+    }
+
+    ///returns reverted changes
+    pub fn rollback(&mut self) -> Result<Vec<Change>, Box<dyn Error>> {
+        let changes = self.journal.rollback()?;
+        self.revert(&changes);
+        Ok(changes)
+    }
+
+    ///returns reverted changes
+    pub fn undo(&mut self) -> Result<Vec<Change>, Box<dyn Error>> {
+        let changes = self.journal.undo()?;
+        self.revert(&changes);
+        Ok(changes)
+    }
+
+    ///returns changes made again
+    pub fn redo(&mut self) -> Result<Vec<Change>, Box<dyn Error>> {
+        let changes = self.journal.redo()?;
+        for change in &changes {
+            self.set(&change.name, change.after.clone());
+        }
+        Ok(changes)
+    }
+
+    fn revert(&mut self, changes: &[Change]) {
+        for change in changes.iter().rev() {
+            self.set(&change.name, change.before.clone());
+        }
+    }
+
+    fn set(&mut self, name: &str, value: Option<Entry>) {
+        match value {
+            Some(value) => self.data.insert(name.to_owned(), value),
+            None => self.data.remove(name),
+        };
+    }
+
+    ///differences from the file as it was last loaded or saved, by name
+    pub fn unsaved_changes(&self) -> Vec<Change> {
+        let names = self
+            .base
+            .keys()
+            .chain(self.data.keys())
+            .collect::<BTreeSet<_>>();
+        names
+            .into_iter()
+            .filter(|&name| self.base.get(name) != self.data.get(name))
+            .map(|name| Change {
+                name: name.clone(),
+                before: self.base.get(name).cloned(),
+                after: self.data.get(name).cloned(),
+            })
+            .collect()
+    }
+
+    ///file must exist, use an empty one for a new database. `password` is
+    ///needed for encrypted files, a plain one will be encrypted on next save
+    pub fn load_from_path(
+        path: &Path,
+        password: Option<String>,
+        mode: LockMode,
+    ) -> Result<(Database, Loaded), Box<dyn Error>> {
         let mut database = Database {
             data: Default::default(),
+            path: path.to_owned(),
+            password,
+            base: Default::default(),
+            disk_digest: None,
+            disk_password: None,
+            from_backup: false,
+            lock: DatabaseLock::acquire(path, mode)?,
+            journal: Journal::default(),
+        };
+
+        let loaded = database.reload()?;
+        Ok((database, loaded))
+    }
+
+    ///lets this process open the file again, the lock is taken back with
+    ///`lock_again`
+    pub fn release_lock(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(self.lock.release()?)
+    }
+
+    pub fn lock_again(&mut self) -> Result<(), Box<dyn Error>> {
+        self.lock.take()
+    }
+
+    pub fn is_protected(&self) -> bool {
+        self.password.is_some()
+    }
+
+    ///takes effect on next save, `None` stores keys in plain text
+    pub fn change_password(&mut self, password: Option<String>) {
+        self.password = password;
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn backup_path(&self) -> PathBuf {
+        with_suffix(&self.path, ".bak")
+    }
+
+    ///reads the file again, falling back to the backup if it is damaged
+    pub fn reload(&mut self) -> Result<Loaded, Box<dyn Error>> {
+        self.journal.clear();
+        let password = self.password.as_deref();
+        let (error, digest) = match fs::read(&self.path) {
+            Ok(content) => {
+                let digest = file_digest(&content);
+                let sealed = protection::is_sealed(&content);
+                match parse_entries(content, password) {
+                    Ok(entries) => {
+                        self.data = entries;
+                        self.base = self.data.clone();
+                        self.disk_digest = Some(digest);
+                        self.disk_password = self.password.clone().filter(|_| sealed);
+                        self.from_backup = false;
+                        return Ok(Loaded::Main);
+                    }
+                    //backup is encrypted the same way, it will not help
+                    Err(e) if e.is::<PasswordError>() => return Err(e),
+                    Err(e) => (e, Some(digest)),
+                }
+            }
+            Err(e) => (e.into(), None),
+        };
+
+        let backup = self.backup_path();
+        if !backup.exists() {
+            return Err(error);
+        }
+        let content = fs::read(&backup)?;
+        let sealed = protection::is_sealed(&content);
+        self.data = parse_entries(content, password).map_err(|backup_error| {
+            format!(
+                "{error}; backup {} is damaged too: {backup_error}",
+                backup.display()
+            )
+        })?;
+        self.disk_password = self.password.clone().filter(|_| sealed);
+        self.from_backup = true;
+        //damaged file is what others see, saving over it is not a conflict
+        self.base = self.data.clone();
+        self.disk_digest = digest;
+
+        Ok(Loaded::Backup {
+            error: error.to_string(),
+        })
+    }
+
+    ///The file is replaced atomically, so a failed save leaves the previous
+    ///version in place. If someone else changed the file since it was loaded,
+    ///saving fails unless `merge` is set. Returns whether such changes were merged
+    pub fn save(&mut self, options: SaveOptions) -> Result<bool, Box<dyn Error>> {
+        if self.journal.in_transaction() {
+            return Err("a transaction is open, COMMIT or ROLLBACK it before saving".into());
+        }
+        //the file must not be replaced by others between reading and replacing it
+        let _saving = self.lock.saving(&self.path)?;
+        let current = fs::read(&self.path).ok();
+        let changed = current
+            .as_ref()
+            .is_some_and(|content| Some(file_digest(content)) != self.disk_digest);
+        if changed && !options.merge {
+            return Err(
+                "database file was changed by someone else since it was loaded. \
+                 SAVE MERGE to combine the changes or RELOAD to discard yours"
+                    .into(),
+            );
+        }
+        if let (true, Some(current)) = (changed, &current) {
+            let theirs = parse_entries(current.clone(), self.password.as_deref())
+                .map_err(|e| format!("failed to read changes made by others: {e}"))?;
+            self.data = merge(&self.base, &self.data, &theirs)?;
+        }
+
+        let mut records = self
+            .data
+            .iter()
+            .map(|(k, v)| Ok(record(k, &write_entry(v, options.compact)?)))
+            .collect::<Result<Vec<String>, Box<dyn Error>>>()?;
+        //stable order keeps changes of the file small
+        records.sort();
+        let content = format!("{HEADER} {FORMAT_VERSION}\n{}", records.join(""));
+        let content = match &self.password {
+            Some(password) => protection::seal(password, content.as_bytes())?,
+            None => content.into_bytes(),
+        };
+
+        let backup = self.backup_path();
+        let temporary = with_suffix(&backup, ".tmp");
+        //a damaged file must not replace the backup the keys were read from
+        let previous = if self.from_backup {
+            fs::read(&backup).ok()
+        } else {
+            current
+        };
+        match previous {
+            Some(previous) if options.backup => {
+                fs::write(&temporary, self.backup_content(previous)?)?;
+                File::open(&temporary)?.sync_all()?;
+                fs::rename(&temporary, &backup)?;
+            }
+            //keys in the old backup are readable without the new password
+            _ if self.disk_password != self.password => {
+                for stale in [&backup, &temporary] {
+                    match fs::remove_file(stale) {
+                        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        replace_file(&self.path, &content)
+            .map_err(|e| format!("failed to save database, the file is unchanged: {e}"))?;
+        sync_directory(&self.path)?;
+
+        self.base = self.data.clone();
+        self.disk_digest = Some(file_digest(&content));
+        self.disk_password = self.password.clone();
+        self.from_backup = false;
+        Ok(changed)
+    }
+
+    ///the file as it is on disk, encrypted again if the password was changed
+    fn backup_content(&self, current: Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+        if self.disk_password == self.password {
+            return Ok(current);
+        }
+        let plain = match &self.disk_password {
+            Some(password) => protection::open(password, &current)
+                .map_err(|e| format!("failed to back up the database: {e}"))?,
+            None => current,
+        };
+        match &self.password {
+            Some(password) => protection::seal(password, &plain),
+            None => Ok(plain),
+        }
+    }
+}
+
+fn file_digest(content: &[u8]) -> [u8; 32] {
+    Sha256::digest(content).into()
+}
+
+///lock on a separate file, the database itself is replaced on save. The
+///file lists holders of the lock to tell others who has the database open,
+///a `pid` line for exclusive and `pid shared` lines for shared holders
+struct DatabaseLock {
+    file: File,
+    mode: LockMode,
+    held: bool,
+}
+
+impl DatabaseLock {
+    fn acquire(path: &Path, mode: LockMode) -> Result<DatabaseLock, Box<dyn Error>> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(with_suffix(path, ".lock"))?;
+
+        let mut lock = DatabaseLock {
             file,
+            mode,
+            held: false,
         };
+        lock.take()?;
+        Ok(lock)
+    }
 
-        database.reload()?;
-        Ok(database)
-    }
-
-    pub fn reload(&mut self) -> Result<(), Box<dyn Error>> {
-        self.file.seek(std::io::SeekFrom::Start(0))?;
-        let mut data = String::new();
-        self.file.read_to_string(&mut data)?;
-
-        let entries = data
-            .split('\n')
-            .filter(|s| !s.is_empty())
-            .map(|substring| {
-                let maybe_record: Result<(&str, &str), Box<dyn Error>> = substring
-                    .split_once(':')
-                    .ok_or_else(|| "failed to read database".into());
-                let (name, config) = maybe_record?;
-                let mut deserializer = Deserializer::new(config.as_bytes());
-                Ok((name.to_string(), deserializer.read()?))
-            })
-            .collect::<Result<HashMap<String, StackedCypher>, Box<dyn Error>>>()?;
+    fn take(&mut self) -> Result<(), Box<dyn Error>> {
+        let locked = match self.mode {
+            LockMode::Exclusive => self.file.try_lock(),
+            LockMode::Shared => self.file.try_lock_shared(),
+        };
+        match locked {
+            Ok(()) => {
+                self.held = true;
+                //holders are informational, so failing to record them is not an error
+                let _ = self.record();
+                Ok(())
+            }
+            Err(TryLockError::WouldBlock) => {
+                let holders = read_holders(&mut self.file).unwrap_or_default();
+                let pids = holders
+                    .iter()
+                    .map(|holder| holder.split(' ').next().unwrap_or_default())
+                    .collect::<Vec<_>>();
+                let shared = holders.iter().any(|holder| holder.ends_with(" shared"));
+                Err(match pids.as_slice() {
+                    [] => "database is locked by another process".to_string(),
+                    [pid] if !shared => format!("database is locked by pid {pid}"),
+                    [pid] => format!("database is locked by pid {pid} (opened SHARED)"),
+                    pids => format!(
+                        "database is locked by pids {} (opened SHARED)",
+                        pids.join(", ")
+                    ),
+                }
+                .into())
+            }
+            Err(TryLockError::Error(e)) => Err(format!("failed to lock database: {e}").into()),
+        }
+    }
 
-        self.data = entries;
+    ///held while the database is saved, others opened it SHARED and may
+    ///save at the same time. Exclusive holders are alone and get `None`
+    fn saving(&self, path: &Path) -> std::io::Result<Option<File>> {
+        if self.mode == LockMode::Exclusive {
+            return Ok(None);
+        }
+        let file = File::options()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(with_suffix(path, ".save-lock"))?;
+        file.lock()?;
+        Ok(Some(file))
+    }
 
+    fn release(&mut self) -> std::io::Result<()> {
+        if self.held {
+            let _ = self.forget();
+            self.file.unlock()?;
+            self.held = false;
+        }
         Ok(())
     }
 
-    pub fn save(&mut self) -> Result<(), Box<dyn Error>> {
-        self.file.seek(std::io::SeekFrom::Start(0))?;
+    fn record(&mut self) -> std::io::Result<()> {
+        let pid = std::process::id();
+        let holders = match self.mode {
+            LockMode::Exclusive => vec![pid.to_string()],
+            //exclusive holders cannot be there any more
+            LockMode::Shared => {
+                let own = format!("{pid} shared");
+                read_holders(&mut self.file)?
+                    .into_iter()
+                    .filter(|holder| holder.ends_with(" shared") && *holder != own)
+                    .chain([own.clone()])
+                    .collect()
+            }
+        };
+        write_holders(&mut self.file, &holders)
+    }
 
-        self.file.set_len(0)?;
+    ///removes own line from the list of holders
+    fn forget(&mut self) -> std::io::Result<()> {
+        let pid = std::process::id().to_string();
+        let others = read_holders(&mut self.file)?
+            .into_iter()
+            .filter(|holder| holder.split(' ').next() != Some(pid.as_str()))
+            .collect::<Vec<_>>();
+        write_holders(&mut self.file, &others)
+    }
+}
 
-        let content = self
-            .data
+impl Drop for DatabaseLock {
+    fn drop(&mut self) {
+        if self.held {
+            let _ = self.forget();
+        }
+    }
+}
+
+fn read_holders(file: &mut File) -> std::io::Result<Vec<String>> {
+    let mut content = String::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_string(&mut content)?;
+    Ok(content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+fn write_holders(file: &mut File, holders: &[String]) -> std::io::Result<()> {
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(
+        holders
             .iter()
-            .map(|(k, v)| {
-                let mut buffer = BufWriter::new(Vec::new());
+            .map(|holder| holder.clone() + "\n")
+            .collect::<String>()
+            .as_bytes(),
+    )
+}
+
+///three-way merge: a key changed on one side only takes that change
+fn merge(
+    base: &HashMap<String, Entry>,
+    ours: &HashMap<String, Entry>,
+    theirs: &HashMap<String, Entry>,
+) -> Result<HashMap<String, Entry>, Box<dyn Error>> {
+    let names = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect::<BTreeSet<_>>();
+
+    let mut merged = HashMap::new();
+    let mut conflicts = vec![];
+    for name in names {
+        let (original, mine, other) = (base.get(name), ours.get(name), theirs.get(name));
+        let value = if mine == other || other == original {
+            mine
+        } else if mine == original {
+            other
+        } else {
+            conflicts.push(format!("\"{name}\""));
+            continue;
+        };
+        if let Some(value) = value {
+            merged.insert(name.clone(), value.clone());
+        }
+    }
 
-                Serializer::new(&mut buffer).write(v)?;
+    if !conflicts.is_empty() {
+        return Err(format!(
+            "keys {} were changed both here and by someone else, RELOAD to discard your changes",
+            conflicts.join(", ")
+        )
+        .into());
+    }
+    Ok(merged)
+}
+
+fn parse_entries(
+    content: Vec<u8>,
+    password: Option<&str>,
+) -> Result<HashMap<String, Entry>, Box<dyn Error>> {
+    let content = match (protection::is_sealed(&content), password) {
+        (true, Some(password)) => protection::open(password, &content)?,
+        (true, None) => return Err(PasswordError::Missing.into()),
+        (false, _) => content,
+    };
+    let mut data = String::from_utf8(content)?;
+
+    let version = match data.split('\n').next().and_then(|l| l.strip_prefix(HEADER)) {
+        Some(version) => version
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| format!("invalid database version \"{}\"", version.trim()))?,
+        None => 1,
+    };
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(format!(
+            "database has format version {version}, this program reads 1 to {FORMAT_VERSION}"
+        )
+        .into());
+    }
+    for migration in &MIGRATIONS[version - 1..] {
+        data = migration(&data)?;
+    }
+
+    data.split('\n')
+        .skip(1)
+        .enumerate()
+        .filter(|(_, s)| !s.is_empty())
+        .map(|(i, line)| {
+            let (name, body) = parse_record(line)
+                .map_err(|e| format!("record {} of database is damaged: {e}", i + 1))?;
+            let entry =
+                parse_entry(body).map_err(|e| format!("metadata of \"{name}\" is damaged: {e}"))?;
+            Ok((name, entry))
+        })
+        .collect()
+}
 
-                let string = String::from_utf8(buffer.into_inner()?)?;
+///files from before versioning: `name:serialized` lines, names could not
+///contain `:` or line breaks
+fn migrate_unversioned(data: &str) -> Result<String, Box<dyn Error>> {
+    let records = data
+        .split('\n')
+        .filter(|s| !s.is_empty())
+        .map(|line| {
+            let (name, config) = line.split_once(':').ok_or("failed to read database")?;
+            Ok(record(name, config))
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    Ok(format!("{HEADER} 2\n{}", records.join("")))
+}
+
+///version 2 had no metadata, the serialized key followed the checksum
+fn migrate_to_metadata(data: &str) -> Result<String, Box<dyn Error>> {
+    let records = data
+        .split('\n')
+        .skip(1)
+        .filter(|s| !s.is_empty())
+        .map(|line| {
+            let (name, config) = parse_record(line)?;
+            Ok(record(&name, &format!("; {config}")))
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    Ok(format!("{HEADER} 3\n{}", records.join("")))
+}
+
+///version 4 added fields for key versions, records of version 3 have the
+///first version without history
+fn migrate_to_history(data: &str) -> Result<String, Box<dyn Error>> {
+    let (_, records) = data.split_once('\n').unwrap_or((data, ""));
+    Ok(format!("{HEADER} 4\n{records}"))
+}
+
+///`"name" checksum body` line, body is `metadata ; serialized` since version 3
+fn record(name: &str, body: &str) -> String {
+    format!("{} {} {body}\n", quote(name), checksum(name, body))
+}
 
-                Ok(format!("{}:{}", k, string))
+fn serialize(cypher: &StackedCypher, compact: bool) -> Result<String, Box<dyn Error>> {
+    let mut buffer = BufWriter::new(Vec::new());
+    Serializer::new(&mut buffer)
+        .compact(compact)
+        .write(cypher)?;
+    Ok(String::from_utf8(buffer.into_inner()?)?)
+}
+
+///`key=value ` fields followed by `; ` and the serialized key
+fn write_entry(entry: &Entry, compact: bool) -> Result<String, Box<dyn Error>> {
+    let metadata = &entry.metadata;
+    let mut fields = format!("version={} ", entry.version);
+    for revision in &entry.history {
+        let value = format!(
+            "{} {} {}",
+            revision.version,
+            revision.retired,
+            serialize(&revision.cypher, compact)?
+        );
+        fields += &format!("revision={} ", quote(&value));
+    }
+    if let Some(created) = metadata.created {
+        fields += &format!("created={created} ");
+    }
+    if let Some(modified) = metadata.modified {
+        fields += &format!("modified={modified} ");
+    }
+    if let Some(expires) = metadata.expires {
+        fields += &format!("expires={expires} ");
+    }
+    if let Some(owner) = &metadata.owner {
+        fields += &format!("owner={} ", quote(owner));
+    }
+    if let Some(note) = &metadata.note {
+        fields += &format!("note={} ", quote(note));
+    }
+    for tag in &metadata.tags {
+        fields += &format!("tag={} ", quote(tag));
+    }
+    Ok(fields + "; " + &serialize(&entry.cypher, compact)?)
+}
+
+fn parse_entry(body: &str) -> Result<Entry, Box<dyn Error>> {
+    let mut entry = Entry::new(StackedCypher::new(), Metadata::default());
+    let metadata = &mut entry.metadata;
+    let mut rest = body;
+
+    while !rest.starts_with("; ") {
+        let (field, value) = rest.split_once('=').ok_or("unterminated metadata")?;
+        let quoted = matches!(field, "owner" | "note" | "tag" | "revision");
+        let (token, after) = if quoted {
+            let (token, after) = unquote(value)?;
+            (
+                token,
+                after.strip_prefix(' ').ok_or("unterminated metadata")?,
+            )
+        } else {
+            let (token, after) = value.split_once(' ').ok_or("unterminated metadata")?;
+            (token.to_string(), after)
+        };
+        rest = after;
+
+        match field {
+            "version" => entry.version = token.parse()?,
+            "revision" => {
+                let (version, rest) = token.split_once(' ').ok_or("invalid revision")?;
+                let (retired, config) = rest.split_once(' ').ok_or("invalid revision")?;
+                entry.history.push(Revision {
+                    version: version.parse()?,
+                    retired: retired.parse()?,
+                    cypher: Deserializer::new(config.as_bytes()).read()?,
+                });
+            }
+            "created" => metadata.created = Some(token.parse()?),
+            "modified" => metadata.modified = Some(token.parse()?),
+            "expires" => metadata.expires = Some(token.parse::<Date>()?),
+            "owner" => metadata.owner = Some(token),
+            "note" => metadata.note = Some(token),
+            "tag" => metadata.tags.push(token),
+            other => return Err(format!("unknown metadata field {other}").into()),
+        }
+    }
+    entry.cypher = Deserializer::new(&rest.as_bytes()[2..]).read()?;
+    Ok(entry)
+}
+
+fn parse_record(line: &str) -> Result<(String, &str), Box<dyn Error>> {
+    let (name, rest) = unquote(line)?;
+    let (sum, config) = rest
+        .strip_prefix(' ')
+        .and_then(|rest| rest.split_once(' '))
+        .ok_or("checksum is missing")?;
+    if sum != checksum(&name, config) {
+        return Err(format!("checksum of \"{name}\" does not match").into());
+    }
+    Ok((name, config))
+}
+
+fn checksum(name: &str, config: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(CHECKSUM_DOMAIN)
+        .chain_update(name)
+        .chain_update([0])
+        .chain_update(config)
+        .finalize();
+    digest[..8].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+///`Alice@2` is `("Alice", 2)`
+pub fn split_version(key_name: &str) -> Option<(&str, usize)> {
+    let (name, version) = key_name.rsplit_once('@')?;
+    Some((name, version.parse().ok()?))
+}
+
+fn quote(name: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in name.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+///reads a name written by `quote` and returns the rest of the line
+fn unquote(s: &str) -> Result<(String, &str), Box<dyn Error>> {
+    let mut chars = s
+        .strip_prefix('"')
+        .ok_or("name must be quoted")?
+        .char_indices();
+    let mut name = String::new();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((name, &s[i + 2..])),
+            '\\' => match chars.next().map(|(_, c)| c) {
+                Some('"') => name.push('"'),
+                Some('\\') => name.push('\\'),
+                Some('n') => name.push('\n'),
+                Some('r') => name.push('\r'),
+                Some('t') => name.push('\t'),
+                Some('u') => {
+                    let mut code = chars.by_ref().map(|(_, c)| c);
+                    let digits = match code.next() {
+                        Some('{') => code.take_while(|&c| c != '}').collect::<String>(),
+                        _ => String::new(),
+                    };
+                    let c = u32::from_str_radix(&digits, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or("invalid \\u escape in name")?;
+                    name.push(c);
+                }
+                _ => return Err("invalid escape in name".into()),
+            },
+            c => name.push(c),
+        }
+    }
+    Err("name is not terminated".into())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    name.into()
+}
+
+///writes a temporary file next to `path` and renames it over, so `path` has
+///either the old or the new content even if writing fails. The rename is
+///durable after `sync_directory`
+pub fn replace_file(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let temporary = with_suffix(path, ".tmp");
+    let written = write_synced(&temporary, content).and_then(|_| fs::rename(&temporary, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    written
+}
+
+fn write_synced(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+///makes the rename itself durable
+#[cfg(unix)]
+pub fn sync_directory(path: &Path) -> std::io::Result<()> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(directory)?.sync_all()
+}
+
+#[cfg(not(unix))]
+pub fn sync_directory(_: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        error::Error,
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use crate::algorithms::{permutation::SimplePermutation, stacked::StackedCypher};
+
+    use crate::algorithms::stacked::{EncryptionStyle::*, PadApproach::*};
+
+    use crate::protection::PasswordError;
+
+    use crate::metadata::Metadata;
+
+    use super::{with_suffix, Database, Entry, Loaded, LockMode, SaveOptions};
+
+    fn temporary_database(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("permutations-{}-{name}", std::process::id()));
+        fs::write(&path, "").unwrap();
+        path
+    }
+
+    fn remove_database(path: &Path) {
+        for suffix in ["", ".bak", ".lock", ".save-lock"] {
+            let _ = fs::remove_file(with_suffix(path, suffix));
+        }
+    }
+
+    ///shared, so that tests can open a database several times
+    fn open(path: &Path, password: Option<String>) -> Result<(Database, Loaded), Box<dyn Error>> {
+        Database::load_from_path(path, password, LockMode::Shared)
+    }
+
+    fn entry(cypher: &StackedCypher) -> Entry {
+        Entry::new(cypher.clone(), Metadata::default())
+    }
+
+    fn options(backup: bool) -> SaveOptions {
+        SaveOptions {
+            compact: false,
+            backup,
+            merge: false,
+        }
+    }
+
+    #[test]
+    fn saves_atomically_and_recovers_from_backup() {
+        let path = temporary_database("keys.db");
+        let (mut database, loaded) = open(&path, None).unwrap();
+        assert!(matches!(loaded, Loaded::Main));
+
+        let mut cypher = StackedCypher::new();
+        cypher.push(Padding, Char, SimplePermutation::trivial(3));
+        database.add("first", entry(&cypher));
+        database.save(options(true)).unwrap();
+        database.add("second", entry(&cypher));
+        database.save(options(true)).unwrap();
+
+        assert!(!with_suffix(&path, ".tmp").exists());
+        let (backup, _) = open(&database.backup_path(), None).unwrap();
+        assert_eq!(backup.get_inner().len(), 1);
+
+        fs::write(&path, "damaged").unwrap();
+        assert!(matches!(database.reload().unwrap(), Loaded::Backup { .. }));
+        assert_eq!(database.get("first"), Some(&cypher));
+        assert_eq!(database.get("second"), None);
+
+        //the backup is kept, the damaged file is not copied over it
+        database.save(options(true)).unwrap();
+        let (backup, _) = open(&database.backup_path(), None).unwrap();
+        assert_eq!(backup.get("first"), Some(&cypher));
+        drop(backup);
+        assert!(matches!(database.reload().unwrap(), Loaded::Main));
+        database.save(options(true)).unwrap();
+        let (backup, _) = open(&database.backup_path(), None).unwrap();
+        assert_eq!(backup.get_inner().len(), 1);
+        drop(backup);
+
+        fs::write(&path, "damaged").unwrap();
+        fs::write(database.backup_path(), "damaged too").unwrap();
+        assert!(database.reload().is_err());
+
+        remove_database(&path);
+    }
+
+    #[test]
+    fn names_are_escaped_and_records_checked() {
+        let path = temporary_database("names.db");
+        let (mut database, _) = open(&path, None).unwrap();
+
+        let mut cypher = StackedCypher::new();
+        cypher.push(Padding, Char, SimplePermutation::trivial(3));
+        let names = ["a:b", "line\nbreak", "\"quoted\" \\", "\u{1}\t", ""];
+        for name in names {
+            database.add(name, entry(&cypher));
+        }
+        database.save(options(false)).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("permutations database 4\n"));
+        assert_eq!(content.lines().count(), names.len() + 1);
+
+        let (reloaded, _) = open(&path, None).unwrap();
+        for name in names {
+            assert_eq!(reloaded.get(name), Some(&cypher), "{name:?}");
+        }
+
+        let damaged = content.replacen("char", "byte", 1);
+        fs::write(&path, damaged).unwrap();
+        let error = open(&path, None).err().unwrap().to_string();
+        assert!(error.contains("checksum"), "{error}");
+
+        fs::write(&path, "permutations database 99\n").unwrap();
+        assert!(open(&path, None).is_err());
+
+        remove_database(&path);
+    }
+
+    #[test]
+    fn password_protected_files() {
+        let path = temporary_database("protected.db");
+        let (mut database, _) = open(&path, Some("secret".into())).unwrap();
+
+        let mut cypher = StackedCypher::new();
+        cypher.push(Padding, Char, SimplePermutation::trivial(3));
+        database.add("Alice", entry(&cypher));
+        database.save(options(true)).unwrap();
+        assert!(!fs::read(&path).unwrap().windows(5).any(|w| w == b"Alice"));
+
+        let password_error = |password: Option<&str>| {
+            open(&path, password.map(str::to_string))
+                .err()
+                .and_then(|e| e.downcast::<PasswordError>().ok())
+                .map(|e| *e)
+        };
+        assert_eq!(password_error(None), Some(PasswordError::Missing));
+        //the backup is encrypted too, it is not used instead
+        assert_eq!(password_error(Some("guess")), Some(PasswordError::Wrong));
+        let (mut reloaded, _) = open(&path, Some("secret".into())).unwrap();
+        assert_eq!(reloaded.get("Alice"), Some(&cypher));
+
+        reloaded.change_password(None);
+        reloaded.save(options(false)).unwrap();
+        let (plain, _) = open(&path, None).unwrap();
+        assert_eq!(plain.get("Alice"), Some(&cypher));
+
+        remove_database(&path);
+    }
+
+    #[test]
+    fn changing_password_protects_backup() {
+        let path = temporary_database("rekeyed.db");
+        let backup = with_suffix(&path, ".bak");
+        let has_names = |path: &Path| fs::read(path).unwrap().windows(5).any(|w| w == b"Alice");
+        let (mut database, _) = open(&path, None).unwrap();
+
+        let mut cypher = StackedCypher::new();
+        cypher.push(Padding, Char, SimplePermutation::trivial(3));
+        database.add("Alice", entry(&cypher));
+        database.save(options(false)).unwrap();
+
+        //the plain file is encrypted again for the backup
+        database.change_password(Some("secret".into()));
+        database.save(options(true)).unwrap();
+        assert!(!has_names(&path));
+        assert!(!has_names(&backup));
+        let (restored, _) = open(&backup, Some("secret".into())).unwrap();
+        assert_eq!(restored.get("Alice"), Some(&cypher));
+        drop(restored);
+
+        //the old password does not open the backup either
+        database.change_password(Some("another".into()));
+        database.save(options(true)).unwrap();
+        let (restored, _) = open(&backup, Some("another".into())).unwrap();
+        assert_eq!(restored.get("Alice"), Some(&cypher));
+        drop(restored);
+
+        //without a new backup the stale one is removed
+        database.change_password(None);
+        database.save(options(false)).unwrap();
+        assert!(has_names(&path));
+        assert!(!backup.exists());
+
+        remove_database(&path);
+    }
+
+    #[test]
+    fn migrates_unversioned_files() {
+        let path = temporary_database("legacy.db");
+        fs::write(
+            &path,
+            "Alice:1 padding char simple 3 2 1 0 \nBob:1 unpadding byte ranked 3.5 ",
+        )
+        .unwrap();
+
+        let (mut database, _) = open(&path, None).unwrap();
+        assert_eq!(database.get_inner().len(), 2);
+        let alice = database.get("Alice").unwrap().clone();
+
+        database.save(options(false)).unwrap();
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("permutations database 4\n"));
+        let (upgraded, _) = open(&path, None).unwrap();
+        assert_eq!(upgraded.get("Alice"), Some(&alice));
+
+        remove_database(&path);
+    }
+
+    #[test]
+    fn keeps_metadata() {
+        let path = temporary_database("metadata.db");
+        fs::write(
+            &path,
+            "permutations database 2\n\"Alice\" ae418c7012685ab6 1 padding char simple 3 2 1 0 \n",
+        )
+        .unwrap();
+
+        let (mut database, _) = open(&path, None).unwrap();
+        let alice = database.get_entry("Alice").unwrap().clone();
+        assert_eq!(alice.metadata, Metadata::default());
+
+        let metadata = Metadata {
+            note: Some("shared with \"Bob\"; rotate yearly".into()),
+            tags: vec!["prod".into(), "a b".into()],
+            owner: Some("alice".into()),
+            created: Some(1_700_000_000),
+            modified: Some(1_700_000_001),
+            expires: Some("2027-01-01".parse().unwrap()),
+        };
+        database.add(
+            "Alice",
+            Entry {
+                metadata: metadata.clone(),
+                ..alice
+            },
+        );
+        database.save(options(false)).unwrap();
+
+        let (reloaded, _) = open(&path, None).unwrap();
+        assert_eq!(reloaded.get_entry("Alice").unwrap().metadata, metadata);
+
+        remove_database(&path);
+    }
+
+    #[test]
+    fn keeps_key_history() {
+        let path = temporary_database("history.db");
+        let (mut database, _) = open(&path, None).unwrap();
+
+        let versions = (2..6)
+            .map(|size| {
+                let mut cypher = StackedCypher::new();
+                cypher.push(Padding, Char, SimplePermutation::trivial(size));
+                cypher
             })
-            .collect::<Result<Vec<String>, Box<dyn Error>>>()?
-            .join("\n");
+            .collect::<Vec<_>>();
+        let mut alice = entry(&versions[0]);
+        for (i, cypher) in versions.iter().enumerate().skip(1) {
+            alice = alice.replaced_with(cypher.clone(), Metadata::default(), i as u64);
+        }
+        database.add("Alice", alice);
+        database.save(options(false)).unwrap();
 
-        self.file.write_all(content.as_bytes())?;
-        self.file.flush()?;
+        let (mut reloaded, _) = open(&path, None).unwrap();
+        assert_eq!(reloaded.get_entry("Alice").unwrap().version, 4);
+        assert_eq!(reloaded.get("Alice"), Some(&versions[3]));
+        assert_eq!(reloaded.get("Alice@1"), Some(&versions[0]));
+        assert_eq!(reloaded.get("Alice@4"), Some(&versions[3]));
+        assert_eq!(reloaded.get("Alice@5"), None);
 
-        Ok(())
+        assert!(reloaded.prune("Alice", 0).is_err());
+        assert_eq!(reloaded.prune("Alice", 2).unwrap(), 2);
+        assert_eq!(reloaded.prune("Alice", 2).unwrap(), 0);
+        assert_eq!(reloaded.get("Alice@2"), None);
+        assert_eq!(reloaded.get("Alice@3"), Some(&versions[2]));
+        reloaded.undo().unwrap();
+        assert_eq!(reloaded.get("Alice@1"), Some(&versions[0]));
+
+        remove_database(&path);
+    }
+
+    #[test]
+    fn undo_and_transactions() {
+        let path = temporary_database("journal.db");
+        let (mut database, _) = open(&path, None).unwrap();
+
+        let mut first = StackedCypher::new();
+        first.push(Padding, Char, SimplePermutation::trivial(3));
+        let mut second = StackedCypher::new();
+        second.push(Padding, Byte, SimplePermutation::trivial(2));
+
+        database.add("Alice", entry(&first));
+        database.add("Alice", entry(&second));
+        database.delete("Alice");
+        assert_eq!(database.unsaved_changes().len(), 0);
+
+        assert_eq!(database.undo().unwrap()[0].to_string(), "deleted \"Alice\"");
+        assert_eq!(database.get("Alice"), Some(&second));
+        database.undo().unwrap();
+        assert_eq!(database.get("Alice"), Some(&first));
+        database.redo().unwrap();
+        assert_eq!(database.get("Alice"), Some(&second));
+        assert_eq!(database.unsaved_changes()[0].to_string(), "added \"Alice\"");
+
+        //new edit forgets what could be redone
+        database.add("Bob", entry(&first));
+        assert!(database.redo().is_err());
+
+        database.begin().unwrap();
+        assert!(database.begin().is_err());
+        database.delete("Alice");
+        database.add("Carol", entry(&first));
+        assert!(database.undo().is_err());
+        assert!(database.save(options(false)).is_err());
+        assert_eq!(database.rollback().unwrap().len(), 2);
+        assert_eq!(database.get("Alice"), Some(&second));
+        assert_eq!(database.get("Carol"), None);
+
+        database.begin().unwrap();
+        database.delete("Alice");
+        database.delete("Bob");
+        assert_eq!(database.commit().unwrap(), 2);
+        assert!(database.get_inner().is_empty());
+        assert_eq!(database.undo().unwrap().len(), 2);
+        assert_eq!(database.get_inner().len(), 2);
+
+        database.rename("Alice", "Dave").unwrap();
+        assert!(database.rename("Dave", "Bob").is_err());
+        assert_eq!(database.get("Dave"), Some(&second));
+        assert_eq!(database.undo().unwrap().len(), 2);
+        assert_eq!(database.get("Alice"), Some(&second));
+        assert_eq!(database.get("Dave"), None);
+
+        database.save(options(false)).unwrap();
+        assert!(database.unsaved_changes().is_empty());
+
+        remove_database(&path);
+    }
+
+    #[test]
+    fn locks_and_merges() {
+        let path = temporary_database("shared.db");
+        let exclusive = Database::load_from_path(&path, None, LockMode::Exclusive).unwrap();
+        let error = open(&path, None).err().unwrap().to_string();
+        assert_eq!(
+            error,
+            format!("database is locked by pid {}", std::process::id())
+        );
+        let (mut exclusive, _) = exclusive;
+        exclusive.release_lock().unwrap();
+        drop(open(&path, None).unwrap());
+        exclusive.lock_again().unwrap();
+        assert!(open(&path, None).is_err());
+        drop(exclusive);
+
+        let (mut first, _) = open(&path, None).unwrap();
+        let (mut second, _) = open(&path, None).unwrap();
+        let lock_file = with_suffix(&path, ".lock");
+        let holders = fs::read_to_string(&lock_file).unwrap();
+        assert_eq!(holders, format!("{} shared\n", std::process::id()));
+        //as if other processes opened it too
+        fs::write(&lock_file, holders + "1234 shared\n").unwrap();
+        let error = Database::load_from_path(&path, None, LockMode::Exclusive)
+            .err()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            error,
+            format!(
+                "database is locked by pids {}, 1234 (opened SHARED)",
+                std::process::id()
+            )
+        );
+
+        let mut cypher = StackedCypher::new();
+        cypher.push(Padding, Char, SimplePermutation::trivial(3));
+        let mut other = StackedCypher::new();
+        other.push(Padding, Byte, SimplePermutation::trivial(2));
+
+        first.add("first", entry(&cypher));
+        first.add("shared", entry(&cypher));
+        assert!(!first.save(options(false)).unwrap());
+
+        second.add("second", entry(&cypher));
+        assert!(second.save(options(false)).is_err());
+        let merge = SaveOptions {
+            merge: true,
+            ..options(false)
+        };
+        assert!(second.save(merge).unwrap());
+        assert_eq!(second.get_inner().len(), 3);
+
+        //both change "shared", the second one to save has to choose
+        second.add("shared", entry(&other));
+        assert!(!second.save(options(false)).unwrap());
+        first.add("shared", entry(&StackedCypher::new()));
+        let merge = SaveOptions {
+            merge: true,
+            ..options(false)
+        };
+        let error = first.save(merge).err().unwrap().to_string();
+        assert!(error.contains("\"shared\""), "{error}");
+
+        first.delete("shared");
+        first.reload().unwrap();
+        assert_eq!(first.get("shared"), Some(&other));
+
+        remove_database(&path);
+    }
+
+    #[test]
+    fn shared_saves_do_not_lose_changes() {
+        let path = temporary_database("concurrent.db");
+        let mut cypher = StackedCypher::new();
+        cypher.push(Padding, Char, SimplePermutation::trivial(3));
+
+        let names = (0..8).map(|i| format!("key {i}")).collect::<Vec<_>>();
+        std::thread::scope(|scope| {
+            for name in &names {
+                let (path, cypher) = (&path, &cypher);
+                scope.spawn(move || {
+                    let (mut database, _) = open(path, None).unwrap();
+                    database.add(name, entry(cypher));
+                    let merge = SaveOptions {
+                        merge: true,
+                        ..options(false)
+                    };
+                    database.save(merge).unwrap();
+                });
+            }
+        });
+
+        let (database, _) = open(&path, None).unwrap();
+        for name in &names {
+            assert_eq!(database.get(name), Some(&cypher), "{name}");
+        }
+
+        remove_database(&path);
     }
 }
@@ -1,14 +1,13 @@
-use std::{error::Error, iter::repeat};
+use std::{collections::HashSet, error::Error, hash::Hash, iter::repeat_n};
 
-#[derive(Copy, Clone, Debug, Default)]
+use rand::{seq::SliceRandom, thread_rng};
+
+use crate::algorithms::stacked::PadValue;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Bit(bool);
 
 pub trait ProvidesPad: Sized {
-    fn get_pad(&self, pad_size: usize) -> Vec<Self> {
-        std::iter::repeat_with(|| self.get_pad_value())
-            .take(pad_size)
-            .collect()
-    }
     fn get_pad_value(&self) -> Self;
 }
 
@@ -16,15 +15,191 @@ impl<T> ProvidesPad for T
 where
     T: Default,
 {
-    fn get_pad(&self, size: usize) -> Vec<Self> {
-        std::iter::repeat_with(|| T::default()).take(size).collect()
-    }
-
     fn get_pad_value(&self) -> Self {
         T::default()
     }
 }
 
+///element that can be made from a character or number chosen by user
+pub trait PadElement: ProvidesPad + Clone + Eq + Hash {
+    ///`reference` is an element of the same message, groups take their length from it
+    fn from_pad_code(reference: &Self, code: u32) -> Option<Self>;
+
+    ///element of the message reused as a pad next to `reference`
+    fn reshaped_like(self, _reference: &Self) -> Self {
+        self
+    }
+
+    ///first element of self-delimiting pad, differs from the default pad value
+    fn pad_mark(reference: &Self) -> Self;
+
+    ///number of elements stored together in bytes, delimited pad fills
+    ///whole units so that nothing else trails it
+    fn storage_unit() -> usize {
+        1
+    }
+
+    ///`count` elements (at least one) that can be found and removed after
+    ///decryption without knowing the original length: a mark followed by
+    ///default pad values, as in ISO/IEC 7816-4
+    fn delimiting_pad(reference: &Self, count: usize, _block_size: usize) -> Vec<Self> {
+        std::iter::once(Self::pad_mark(reference))
+            .chain(std::iter::repeat_with(|| reference.get_pad_value()))
+            .take(count)
+            .collect()
+    }
+
+    ///removes pad added by `delimiting_pad`
+    fn strip_delimiting_pad(data: &mut Vec<Self>, block_size: usize) -> Result<(), Box<dyn Error>> {
+        let Some(reference) = data.last().cloned() else {
+            return Ok(());
+        };
+        let fill = reference.get_pad_value();
+
+        let mut removed = 0;
+        while data.last() == Some(&fill) && removed < block_size {
+            data.pop();
+            removed += 1;
+        }
+
+        if data.pop() != Some(Self::pad_mark(&reference)) {
+            return Err("self-delimiting padding is damaged".into());
+        }
+        Ok(())
+    }
+}
+
+///chooses pad elements of one message according to `PadValue`
+pub struct Filler<'a, T> {
+    value: PadValue,
+    candidates: Vec<&'a T>,
+}
+
+impl<'a, T: PadElement> Filler<'a, T> {
+    pub fn new(value: PadValue, data: &'a [T]) -> Result<Self, Box<dyn Error>> {
+        if let (PadValue::Fixed(code), Some(reference)) = (value, data.first()) {
+            if T::from_pad_code(reference, code).is_none() {
+                return Err(format!("pad value {code} can not be used with this element").into());
+            }
+        }
+
+        let candidates = match value {
+            PadValue::Random => {
+                let mut seen = HashSet::new();
+                data.iter().filter(|&item| seen.insert(item)).collect()
+            }
+            //every element is chosen as often as it appears
+            PadValue::Frequency => data.iter().collect(),
+            PadValue::Default | PadValue::Fixed(_) | PadValue::Delimited => vec![],
+        };
+
+        Ok(Filler { value, candidates })
+    }
+
+    pub fn fill(&self, reference: &T) -> T {
+        match self.value {
+            PadValue::Fixed(code) => T::from_pad_code(reference, code),
+            PadValue::Random | PadValue::Frequency => self
+                .candidates
+                .choose(&mut thread_rng())
+                .map(|&item| item.clone().reshaped_like(reference)),
+            PadValue::Default | PadValue::Delimited => None,
+        }
+        .unwrap_or_else(|| reference.get_pad_value())
+    }
+}
+
+impl PadElement for Bit {
+    fn from_pad_code(_: &Self, code: u32) -> Option<Self> {
+        match code {
+            0 => Some(Bit(false)),
+            1 => Some(Bit(true)),
+            _ => None,
+        }
+    }
+
+    fn pad_mark(_: &Self) -> Self {
+        Bit(true)
+    }
+
+    fn storage_unit() -> usize {
+        8
+    }
+}
+
+///width of the field is unknown here, so it is checked by the caller
+impl PadElement for BitField {
+    fn from_pad_code(_: &Self, code: u32) -> Option<Self> {
+        Some(BitField(code))
+    }
+
+    fn pad_mark(_: &Self) -> Self {
+        BitField(1)
+    }
+}
+
+///bytes are padded as in PKCS#7 when the count fits into a byte
+impl PadElement for u8 {
+    fn from_pad_code(_: &Self, code: u32) -> Option<Self> {
+        u8::try_from(code).ok()
+    }
+
+    fn pad_mark(_: &Self) -> Self {
+        0x80
+    }
+
+    fn delimiting_pad(_: &Self, count: usize, block_size: usize) -> Vec<Self> {
+        match u8::try_from(block_size) {
+            Ok(_) => vec![count as u8; count],
+            Err(_) => std::iter::once(0x80)
+                .chain(repeat_n(0, count - 1))
+                .collect(),
+        }
+    }
+
+    fn strip_delimiting_pad(data: &mut Vec<Self>, block_size: usize) -> Result<(), Box<dyn Error>> {
+        if u8::try_from(block_size).is_err() {
+            let Some(end) = data.iter().rposition(|&b| b != 0) else {
+                return Err("self-delimiting padding is damaged".into());
+            };
+            if data[end] != 0x80 || data.len() - end > block_size {
+                return Err("self-delimiting padding is damaged".into());
+            }
+            data.truncate(end);
+            return Ok(());
+        }
+
+        let Some(&count) = data.last() else {
+            return Ok(());
+        };
+        let count = count as usize;
+        if count == 0
+            || count > block_size
+            || count > data.len()
+            || data[data.len() - count..]
+                .iter()
+                .any(|&b| b as usize != count)
+        {
+            return Err("self-delimiting padding is damaged".into());
+        }
+        data.truncate(data.len() - count);
+        Ok(())
+    }
+}
+
+///"end of text" control character
+const PAD_MARK: char = '\u{3}';
+
+impl PadElement for char {
+    fn from_pad_code(_: &Self, code: u32) -> Option<Self> {
+        char::from_u32(code)
+    }
+
+    fn pad_mark(_: &Self) -> Self {
+        PAD_MARK
+    }
+}
+
 pub struct BitVector(pub Vec<Bit>);
 
 impl From<&[u8]> for BitVector {
@@ -58,11 +233,7 @@ pub fn to_bits(mut value: u8) -> Vec<Bit> {
         result.push(Bit((value & 1) != 0));
         value >>= 1;
     }
-    result.append(
-        &mut repeat(Bit(false))
-            .take(8 - result.len())
-            .collect::<Vec<_>>(),
-    );
+    result.append(&mut repeat_n(Bit(false), 8 - result.len()).collect::<Vec<_>>());
     result
 }
 
@@ -79,12 +250,85 @@ pub fn from_bits(bits: &[Bit]) -> u8 {
     result
 }
 
-#[derive(Clone, Debug)]
+pub const MAX_BIT_FIELD_WIDTH: usize = 32;
+
+///`width` lowest bits of a value
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BitField(u32);
+
+///bytes seen as a stream of bits (lowest bit of every byte first, as in
+///`BitVector`) cut into units of `width` bits. Width 1 gives the same stream
+///as `BitVector` and width 8 gives the bytes themselves
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitFieldVector {
+    pub width: usize,
+    pub fields: Vec<BitField>,
+}
+
+impl BitFieldVector {
+    ///the last unit is filled up with zero bits if the bits do not divide evenly
+    pub fn from_bytes(bytes: &[u8], width: usize) -> Result<Self, Box<dyn Error>> {
+        if !(1..=MAX_BIT_FIELD_WIDTH).contains(&width) {
+            return Err(format!(
+                "bit field width must be between 1 and {MAX_BIT_FIELD_WIDTH}, got {width}"
+            )
+            .into());
+        }
+
+        let mut fields = Vec::with_capacity((bytes.len() * 8).div_ceil(width));
+        let (mut current, mut filled) = (0u64, 0);
+        for &byte in bytes {
+            current |= (byte as u64) << filled;
+            filled += 8;
+            while filled >= width {
+                fields.push(BitField((current & ((1 << width) - 1)) as u32));
+                current >>= width;
+                filled -= width;
+            }
+        }
+        if filled > 0 {
+            fields.push(BitField(current as u32));
+        }
+
+        Ok(BitFieldVector { width, fields })
+    }
+
+    ///number of units needed for `bytes` bytes
+    pub fn units_for(bytes: usize, width: usize) -> usize {
+        (bytes * 8).div_ceil(width)
+    }
+}
+
+impl From<BitFieldVector> for Vec<u8> {
+    ///the last byte is filled up with zero bits
+    fn from(value: BitFieldVector) -> Self {
+        let mut result = Vec::with_capacity((value.fields.len() * value.width).div_ceil(8));
+        let (mut current, mut filled) = (0u64, 0);
+        for BitField(field) in value.fields {
+            current |= (field as u64) << filled;
+            filled += value.width;
+            while filled >= 8 {
+                result.push(current as u8);
+                current >>= 8;
+                filled -= 8;
+            }
+        }
+        if filled > 0 {
+            result.push(current as u8);
+        }
+        result
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct CharGroup(Vec<char>);
 
 pub fn groups_from_str(s: &str, group_size: usize) -> Result<Vec<CharGroup>, Box<dyn Error>> {
+    if group_size == 0 {
+        return Err("error while building groups from string: group size is 0".into());
+    }
     let chars: Vec<char> = s.chars().collect();
-    if chars.len() % group_size != 0 {
+    if !chars.len().is_multiple_of(group_size) {
         return Err(format!(
             "error while building groups from string: got {}, expected multiple of {}",
             chars.len(),
@@ -102,8 +346,334 @@ pub fn string_from_groups(groups: &[CharGroup]) -> String {
     groups.iter().cloned().flat_map(|g| g.0).collect()
 }
 
+impl CharGroup {
+    pub fn new(chars: Vec<char>) -> Self {
+        CharGroup(chars)
+    }
+
+    pub fn char_count(&self) -> usize {
+        self.0.len()
+    }
+}
+
+///full groups and chars that are left over after them
+pub fn split_groups(chars: &[char], group_size: usize) -> (Vec<CharGroup>, &[char]) {
+    assert!(group_size > 0, "group size must be at least 1");
+    let full = chars.len() - chars.len() % group_size;
+    let groups = chars[..full]
+        .chunks(group_size)
+        .map(|chunk| CharGroup(chunk.to_vec()))
+        .collect();
+    (groups, &chars[full..])
+}
+
+///groups of given lengths that must cover all chars
+pub fn groups_with_lengths(
+    chars: &[char],
+    lengths: impl IntoIterator<Item = usize>,
+) -> Result<Vec<CharGroup>, Box<dyn Error>> {
+    let mut rest = chars;
+    let mut groups = vec![];
+    for length in lengths {
+        let (group, tail) = rest
+            .split_at_checked(length)
+            .ok_or("error while building groups from string: not enough characters")?;
+        groups.push(CharGroup(group.to_vec()));
+        rest = tail;
+    }
+    if !rest.is_empty() {
+        return Err(format!(
+            "error while building groups from string: {} characters left over",
+            rest.len()
+        )
+        .into());
+    }
+    Ok(groups)
+}
+
 impl ProvidesPad for CharGroup {
     fn get_pad_value(&self) -> Self {
         CharGroup((0..self.0.len()).map(|_| Default::default()).collect())
     }
 }
+
+impl PadElement for CharGroup {
+    fn from_pad_code(reference: &Self, code: u32) -> Option<Self> {
+        let c = char::from_u32(code)?;
+        Some(CharGroup(vec![c; reference.0.len()]))
+    }
+
+    ///short groups of `GroupRemainder::Record` must stay as long as default pads
+    fn reshaped_like(self, reference: &Self) -> Self {
+        CharGroup(self.0.into_iter().cycle().take(reference.0.len()).collect())
+    }
+
+    fn pad_mark(reference: &Self) -> Self {
+        let mut mark = reference.get_pad_value();
+        if let Some(first) = mark.0.first_mut() {
+            *first = PAD_MARK;
+        }
+        mark
+    }
+}
+
+///extended grapheme cluster, what a reader sees as a single character
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Grapheme(String);
+
+pub fn graphemes_from_str(s: &str) -> Vec<Grapheme> {
+    crate::grapheme::graphemes(s)
+        .into_iter()
+        .map(|g| Grapheme(g.to_string()))
+        .collect()
+}
+
+///moved clusters may join their new neighbours (a lone combining mark at the
+///start of text, an unpaired flag letter), which would make the text split
+///differently during decryption
+pub fn string_from_graphemes(graphemes: &[Grapheme]) -> Result<String, Box<dyn Error>> {
+    let string = graphemes.iter().map(|g| g.0.as_str()).collect::<String>();
+    if graphemes_from_str(&string) != graphemes {
+        return Err("grapheme clusters merge after reordering, text can not be split back".into());
+    }
+    Ok(string)
+}
+
+///`\0` is a control character, so it is always a cluster of its own
+impl ProvidesPad for Grapheme {
+    fn get_pad_value(&self) -> Self {
+        Grapheme("\0".to_string())
+    }
+}
+
+///word together with whitespace and punctuation that follow it
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Word(String);
+
+///line together with its line break
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Line(String);
+
+///text split into movable tokens. Separators before the first token and an
+///unterminated last token stay in place, so tokens joined in any order split
+///back into the same tokens
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tokens<T> {
+    pub prefix: String,
+    pub tokens: Vec<T>,
+    pub suffix: String,
+}
+
+fn is_word_separator(c: char) -> bool {
+    c.is_whitespace()
+        || c.is_ascii_punctuation()
+        || matches!(c, '«' | '»' | '—' | '–' | '…' | '„' | '“' | '”')
+}
+
+pub fn words_from_str(s: &str) -> Tokens<Word> {
+    let start = s.find(|c| !is_word_separator(c)).unwrap_or(s.len());
+    let (prefix, mut rest) = s.split_at(start);
+
+    let mut tokens = vec![];
+    while !rest.is_empty() {
+        let Some(word_end) = rest.find(is_word_separator) else {
+            //last word is not followed by anything
+            break;
+        };
+        let token_end = rest[word_end..]
+            .find(|c| !is_word_separator(c))
+            .map_or(rest.len(), |i| word_end + i);
+        tokens.push(Word(rest[..token_end].to_string()));
+        rest = &rest[token_end..];
+    }
+
+    Tokens {
+        prefix: prefix.to_string(),
+        tokens,
+        suffix: rest.to_string(),
+    }
+}
+
+pub fn lines_from_str(s: &str) -> Tokens<Line> {
+    let mut tokens = s
+        .split_inclusive('\n')
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let suffix = match tokens.last() {
+        Some(last) if !last.ends_with('\n') => tokens.pop().unwrap(),
+        _ => String::new(),
+    };
+
+    Tokens {
+        prefix: String::new(),
+        tokens: tokens.into_iter().map(Line).collect(),
+        suffix,
+    }
+}
+
+impl<T> Tokens<T> {
+    pub fn with_tokens<U>(self, tokens: Vec<U>) -> Tokens<U> {
+        Tokens {
+            prefix: self.prefix,
+            tokens,
+            suffix: self.suffix,
+        }
+    }
+}
+
+impl<T: AsRef<str>> From<Tokens<T>> for String {
+    fn from(value: Tokens<T>) -> Self {
+        let mut result = value.prefix;
+        for token in &value.tokens {
+            result.push_str(token.as_ref());
+        }
+        result.push_str(&value.suffix);
+        result
+    }
+}
+
+impl AsRef<str> for Word {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Line {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+///`\0` is not a separator, so padding words are split back as words
+impl ProvidesPad for Word {
+    fn get_pad_value(&self) -> Self {
+        Word("\0 ".to_string())
+    }
+}
+
+impl ProvidesPad for Line {
+    fn get_pad_value(&self) -> Self {
+        Line("\n".to_string())
+    }
+}
+
+impl PadElement for Grapheme {
+    fn from_pad_code(_: &Self, code: u32) -> Option<Self> {
+        char::from_u32(code).map(|c| Grapheme(c.to_string()))
+    }
+
+    fn pad_mark(_: &Self) -> Self {
+        Grapheme(PAD_MARK.to_string())
+    }
+}
+
+///pad word is the character followed by a space, so it must not be a separator
+impl PadElement for Word {
+    fn from_pad_code(_: &Self, code: u32) -> Option<Self> {
+        char::from_u32(code)
+            .filter(|&c| !is_word_separator(c))
+            .map(|c| Word(format!("{c} ")))
+    }
+
+    fn pad_mark(_: &Self) -> Self {
+        Word(format!("{PAD_MARK} "))
+    }
+}
+
+impl PadElement for Line {
+    fn from_pad_code(_: &Self, code: u32) -> Option<Self> {
+        char::from_u32(code).map(|c| match c {
+            '\n' => Line("\n".to_string()),
+            c => Line(format!("{c}\n")),
+        })
+    }
+
+    fn pad_mark(_: &Self) -> Self {
+        Line(format!("{PAD_MARK}\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        groups_from_str, lines_from_str, split_groups, words_from_str, BitFieldVector, BitVector,
+    };
+
+    #[test]
+    fn groups_of_size_zero_are_rejected() {
+        assert!(groups_from_str("hello", 0).is_err());
+        let chars = "hello".chars().collect::<Vec<_>>();
+        let (groups, tail) = split_groups(&chars, 2);
+        assert_eq!((groups.len(), tail), (2, &['o'][..]));
+        assert!(std::panic::catch_unwind(|| split_groups(&chars, 0)).is_err());
+    }
+
+    #[test]
+    fn bit_fields() {
+        let bytes = b"\x01\xf2\x7c";
+
+        let as_bits = BitFieldVector::from_bytes(bytes, 1).unwrap();
+        let bit_vector = BitVector::from(&bytes[..]);
+        assert!(as_bits
+            .fields
+            .iter()
+            .zip(bit_vector.0.iter())
+            .all(|(field, bit)| (field.0 == 1) == bit.0));
+
+        let as_bytes = BitFieldVector::from_bytes(bytes, 8).unwrap();
+        assert!(as_bytes
+            .fields
+            .iter()
+            .zip(bytes)
+            .all(|(f, &b)| f.0 == b as u32));
+
+        let nibbles = BitFieldVector::from_bytes(bytes, 4).unwrap();
+        assert_eq!(
+            nibbles.fields.iter().map(|f| f.0).collect::<Vec<_>>(),
+            vec![1, 0, 2, 15, 12, 7]
+        );
+
+        for width in [3, 5, 6, 12, 32] {
+            let fields = BitFieldVector::from_bytes(bytes, width).unwrap();
+            assert_eq!(
+                fields.fields.len(),
+                BitFieldVector::units_for(bytes.len(), width)
+            );
+            let mut packed: Vec<u8> = fields.into();
+            packed.truncate(bytes.len());
+            assert_eq!(packed, bytes);
+        }
+
+        assert!(BitFieldVector::from_bytes(bytes, 0).is_err());
+        assert!(BitFieldVector::from_bytes(bytes, 33).is_err());
+    }
+
+    #[test]
+    fn words_keep_separators() {
+        let tokens = words_from_str("  Hello, world! How are you");
+        assert_eq!(tokens.prefix, "  ");
+        assert_eq!(
+            tokens.tokens.iter().map(AsRef::as_ref).collect::<Vec<_>>(),
+            vec!["Hello, ", "world! ", "How ", "are "]
+        );
+        assert_eq!(tokens.suffix, "you");
+
+        let mut reordered = tokens.clone();
+        reordered.tokens.reverse();
+        let joined = String::from(reordered.clone());
+        assert_eq!(joined, "  are How world! Hello, you");
+        assert_eq!(words_from_str(&joined), reordered);
+    }
+
+    #[test]
+    fn lines_keep_line_breaks() {
+        let tokens = lines_from_str("first\nsecond\n\nlast");
+        assert_eq!(tokens.prefix, "");
+        assert_eq!(tokens.tokens.len(), 3);
+        assert_eq!(tokens.suffix, "last");
+        assert_eq!(String::from(tokens), "first\nsecond\n\nlast");
+
+        assert_eq!(lines_from_str("one\n").suffix, "");
+        assert!(lines_from_str("").tokens.is_empty());
+    }
+}
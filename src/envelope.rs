@@ -0,0 +1,146 @@
+use std::{
+    error::Error,
+    io::{self, Read, Write},
+};
+
+use crate::algorithms::fingerprint::Fingerprint;
+
+///starts envelopes that carry a key fingerprint. Legacy files start with the
+///number of sizes instead, which never has these bytes on top
+const MAGIC: &[u8; 4] = b"PCE1";
+
+///starts envelopes that carry a key fingerprint and version of the key
+const VERSIONED_MAGIC: &[u8; 4] = b"PCE2";
+
+const TOO_SHORT: &str = "encrypted file is too short";
+
+const USIZE: usize = std::mem::size_of::<usize>();
+
+///encrypted message as it is stored in files: sizes of every layer, encrypted
+///data, fingerprint and version of the key that was used
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Envelope {
+    pub fingerprint: Option<Fingerprint>,
+    ///only stored together with the fingerprint
+    pub key_version: Option<usize>,
+    pub sizes: Vec<usize>,
+    pub data: Vec<u8>,
+}
+
+impl Envelope {
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match (&self.fingerprint, self.key_version) {
+            (Some(fingerprint), Some(version)) => {
+                writer.write_all(VERSIONED_MAGIC)?;
+                writer.write_all(&fingerprint.0)?;
+                writer.write_all(&version.to_be_bytes())?;
+            }
+            (Some(fingerprint), None) => {
+                writer.write_all(MAGIC)?;
+                writer.write_all(&fingerprint.0)?;
+            }
+            (None, _) => {}
+        }
+
+        writer.write_all(&self.sizes.len().to_be_bytes())?;
+        for size in &self.sizes {
+            writer.write_all(&size.to_be_bytes())?;
+        }
+        writer.write_all(&self.data)
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Envelope, Box<dyn Error>> {
+        let mut content = vec![];
+        reader.read_to_end(&mut content)?;
+        let mut content = content.as_slice();
+
+        let versioned = content.starts_with(VERSIONED_MAGIC);
+        let fingerprint = match content
+            .strip_prefix(MAGIC)
+            .or_else(|| content.strip_prefix(VERSIONED_MAGIC))
+        {
+            Some(rest) => {
+                let (fingerprint, rest) = rest.split_at_checked(8).ok_or(TOO_SHORT)?;
+                content = rest;
+                //length is checked by split_at_checked
+                Some(Fingerprint(fingerprint.try_into().unwrap()))
+            }
+            None => None,
+        };
+        let key_version = if versioned {
+            let (version, rest) = content.split_at_checked(USIZE).ok_or(TOO_SHORT)?;
+            content = rest;
+            Some(usize::from_be_bytes(version.try_into().unwrap()))
+        } else {
+            None
+        };
+
+        let (total, mut content) = content.split_at_checked(USIZE).ok_or(TOO_SHORT)?;
+        let total = usize::from_be_bytes(total.try_into().unwrap());
+
+        if total > content.len() / USIZE {
+            return Err("encrypted file is damaged: too many sizes".into());
+        }
+
+        let mut sizes = Vec::with_capacity(total);
+        for _ in 0..total {
+            let (size, rest) = content.split_at_checked(USIZE).ok_or(TOO_SHORT)?;
+            sizes.push(usize::from_be_bytes(size.try_into().unwrap()));
+            content = rest;
+        }
+
+        Ok(Envelope {
+            fingerprint,
+            key_version,
+            sizes,
+            data: content.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::algorithms::fingerprint::Fingerprint;
+
+    use super::Envelope;
+
+    #[test]
+    fn round_trip() {
+        let fingerprint = Fingerprint([1, 2, 3, 4, 5, 6, 7, 8]);
+        for (fingerprint, key_version) in [
+            (None, None),
+            (Some(fingerprint), None),
+            (Some(fingerprint), Some(3)),
+        ] {
+            let envelope = Envelope {
+                fingerprint,
+                key_version,
+                sizes: vec![10, 12],
+                data: b"some data".to_vec(),
+            };
+
+            let mut buffer = vec![];
+            envelope.write_to(&mut buffer).unwrap();
+            assert_eq!(
+                Envelope::read_from(&mut buffer.as_slice()).unwrap(),
+                envelope
+            );
+        }
+    }
+
+    #[test]
+    fn reads_legacy_files() {
+        let mut legacy = vec![];
+        legacy.extend_from_slice(&1usize.to_be_bytes());
+        legacy.extend_from_slice(&3usize.to_be_bytes());
+        legacy.extend_from_slice(b"abc");
+
+        let envelope = Envelope::read_from(&mut legacy.as_slice()).unwrap();
+        assert_eq!(envelope.fingerprint, None);
+        assert_eq!(envelope.key_version, None);
+        assert_eq!(envelope.sizes, vec![3]);
+        assert_eq!(envelope.data, b"abc");
+
+        assert!(Envelope::read_from(&mut &legacy[..10]).is_err());
+    }
+}
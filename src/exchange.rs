@@ -0,0 +1,196 @@
+//! Keys exported into JSON or TOML files to be shared with others or edited
+//! by hand. The format is picked by the extension of the file
+
+use std::{error::Error, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    algorithms::{
+        interchange::{self, LayerSchema},
+        stacked::StackedCypher,
+    },
+    metadata::Metadata,
+};
+
+const FORMAT: &str = "permutations keys";
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Document {
+    format: String,
+    version: u32,
+    keys: Vec<ExportedKey>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExportedKey {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    ///`YYYY-MM-DD`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires: Option<String>,
+    layers: Vec<LayerSchema>,
+}
+
+///key read from a file. Owner and timestamps are not exported
+pub struct Imported {
+    pub name: String,
+    pub cypher: StackedCypher,
+    pub metadata: Metadata,
+}
+
+enum Syntax {
+    Json,
+    Toml,
+}
+
+fn syntax(path: &Path) -> Result<Syntax, Box<dyn Error>> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => Ok(Syntax::Json),
+        Some("toml") => Ok(Syntax::Toml),
+        _ => Err(format!(
+            "unknown format of {}, name the file .json or .toml",
+            path.display()
+        )
+        .into()),
+    }
+}
+
+pub fn export<'k>(
+    path: &Path,
+    keys: impl IntoIterator<Item = (&'k str, &'k StackedCypher, &'k Metadata)>,
+) -> Result<(), Box<dyn Error>> {
+    let document = Document {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        keys: keys
+            .into_iter()
+            .map(|(name, cypher, metadata)| ExportedKey {
+                name: name.to_string(),
+                note: metadata.note.clone(),
+                tags: metadata.tags.clone(),
+                expires: metadata.expires.map(|date| date.to_string()),
+                layers: interchange::describe(cypher),
+            })
+            .collect(),
+    };
+
+    let content = match syntax(path)? {
+        Syntax::Json => serde_json::to_string_pretty(&document)? + "\n",
+        Syntax::Toml => toml::to_string_pretty(&document)?,
+    };
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+pub fn import(path: &Path) -> Result<Vec<Imported>, Box<dyn Error>> {
+    let syntax = syntax(path)?;
+    let content = std::fs::read_to_string(path)?;
+    let document: Document = match syntax {
+        Syntax::Json => serde_json::from_str(&content)?,
+        Syntax::Toml => toml::from_str(&content)?,
+    };
+
+    if document.format != FORMAT {
+        return Err(format!("not a file of keys, format is \"{}\"", document.format).into());
+    }
+    if document.version != VERSION {
+        return Err(format!(
+            "keys have format version {}, this program reads {VERSION}",
+            document.version
+        )
+        .into());
+    }
+
+    document
+        .keys
+        .into_iter()
+        .map(|key| {
+            let invalid = |e: Box<dyn Error>| format!("key \"{}\" is invalid: {e}", key.name);
+            let cypher = interchange::build(&key.layers).map_err(invalid)?;
+            let expires = key
+                .expires
+                .as_deref()
+                .map(str::parse)
+                .transpose()
+                .map_err(invalid)?;
+            Ok(Imported {
+                cypher,
+                metadata: Metadata {
+                    note: key.note,
+                    tags: key.tags,
+                    expires,
+                    ..Default::default()
+                },
+                name: key.name,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        algorithms::{
+            permutation::SimplePermutation,
+            stacked::{EncryptionStyle::*, GroupRemainder, PadApproach::*, StackedCypher},
+            vertical::VerticalPermutation,
+        },
+        metadata::Metadata,
+    };
+
+    use super::{export, import};
+
+    #[test]
+    fn json_and_toml() {
+        let mut cypher = StackedCypher::new();
+        cypher.push(
+            Padding,
+            Group(2, GroupRemainder::Bypass),
+            SimplePermutation::try_from(vec![1, 0]).unwrap(),
+        );
+        cypher.push(
+            Stealing,
+            Char,
+            VerticalPermutation::try_new(2, 3, SimplePermutation::try_from(vec![2, 0, 1]).unwrap())
+                .unwrap(),
+        );
+        let metadata = Metadata {
+            note: Some("shared".into()),
+            tags: vec!["prod".into()],
+            expires: Some("2027-01-01".parse().unwrap()),
+            ..Default::default()
+        };
+
+        for extension in ["json", "toml"] {
+            let path = std::env::temp_dir().join(format!(
+                "permutations-{}-keys.{extension}",
+                std::process::id()
+            ));
+            export(&path, [("Alice", &cypher, &metadata)]).unwrap();
+
+            let imported = import(&path).unwrap();
+            assert_eq!(imported.len(), 1);
+            assert_eq!(imported[0].name, "Alice");
+            assert_eq!(imported[0].cypher, cypher);
+            assert_eq!(imported[0].metadata, metadata);
+
+            let edited = fs::read_to_string(&path)
+                .unwrap()
+                .replace("2027-01-01", "2027-02-30");
+            fs::write(&path, edited).unwrap();
+            let error = import(&path).err().unwrap().to_string();
+            assert!(error.contains("\"Alice\""), "{error}");
+
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Extended grapheme clusters as described in UAX #29 "Unicode Text Segmentation"
+
+mod tables;
+
+use std::cmp::Ordering;
+
+///values of Grapheme_Cluster_Break, named as in UAX #29
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Category {
+    Any,
+    CR,
+    LF,
+    Control,
+    Extend,
+    ZWJ,
+    RegionalIndicator,
+    Prepend,
+    SpacingMark,
+    L,
+    V,
+    T,
+    LV,
+    LVT,
+    ExtendedPictographic,
+    ///Indic_Conjunct_Break=Consonant, otherwise the same as `Any`
+    Consonant,
+}
+
+fn category(c: char) -> Category {
+    tables::CATEGORIES
+        .binary_search_by(|&(low, high, _)| {
+            if high < c {
+                Ordering::Less
+            } else if low > c {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+        .map_or(Category::Any, |i| tables::CATEGORIES[i].2)
+}
+
+fn is_incb_extend(c: char) -> bool {
+    tables::INCB_EXTEND
+        .binary_search_by(|&(low, high)| {
+            if high < c {
+                Ordering::Less
+            } else if low > c {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+fn is_incb_linker(c: char) -> bool {
+    matches!(
+        c,
+        '\u{94D}' | '\u{9CD}' | '\u{ACD}' | '\u{B4D}' | '\u{C4D}' | '\u{D4D}'
+    )
+}
+
+///what was seen before the current position, for rules that look further
+///back than one character
+#[derive(Copy, Clone, Debug, Default)]
+struct Context {
+    ///`ExtendedPictographic Extend*` (GB11)
+    pictographic: bool,
+    ///`ExtendedPictographic Extend* ZWJ` (GB11)
+    pictographic_zwj: bool,
+    ///regional indicators right before the current position (GB12, GB13)
+    regional_indicators: usize,
+    ///`Consonant [Extend Linker]*` (GB9c)
+    consonant: bool,
+    ///`Consonant [Extend Linker]* Linker [Extend Linker]*` (GB9c)
+    consonant_linker: bool,
+}
+
+impl Context {
+    fn update(&mut self, c: char, category: Category) {
+        let pictographic = category == Category::ExtendedPictographic
+            || (self.pictographic && category == Category::Extend);
+        self.pictographic_zwj = self.pictographic && category == Category::ZWJ;
+        self.pictographic = pictographic;
+
+        if category == Category::RegionalIndicator {
+            self.regional_indicators += 1;
+        } else {
+            self.regional_indicators = 0;
+        }
+
+        let in_conjunct = self.consonant && (is_incb_extend(c) || is_incb_linker(c));
+        self.consonant_linker = in_conjunct && (self.consonant_linker || is_incb_linker(c));
+        self.consonant = category == Category::Consonant || in_conjunct;
+        if category == Category::Consonant {
+            self.consonant_linker = false;
+        }
+    }
+
+    fn is_boundary(&self, previous: Category, next: Category) -> bool {
+        use Category::*;
+
+        match (previous, next) {
+            (CR, LF) => false,
+            (CR | LF | Control, _) | (_, CR | LF | Control) => true,
+            (L, L | V | LV | LVT) | (LV | V, V | T) | (LVT | T, T) => false,
+            (_, Extend | ZWJ | SpacingMark) | (Prepend, _) => false,
+            (_, Consonant) if self.consonant_linker => false,
+            (ZWJ, ExtendedPictographic) if self.pictographic_zwj => false,
+            (RegionalIndicator, RegionalIndicator) => self.regional_indicators.is_multiple_of(2),
+            _ => true,
+        }
+    }
+}
+
+///splits text into extended grapheme clusters
+pub fn graphemes(s: &str) -> Vec<&str> {
+    let mut result = vec![];
+    let mut start = 0;
+    let mut context = Context::default();
+    let mut previous = None;
+
+    for (i, c) in s.char_indices() {
+        let category = category(c);
+        if previous.is_some_and(|previous| context.is_boundary(previous, category)) {
+            result.push(&s[start..i]);
+            start = i;
+        }
+        context.update(c, category);
+        previous = Some(category);
+    }
+
+    if start < s.len() {
+        result.push(&s[start..]);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::graphemes;
+
+    #[test]
+    fn clusters() {
+        let cases: &[(&str, &[&str])] = &[
+            ("abc", &["a", "b", "c"]),
+            (
+                "e\u{301}t\u{301}e\u{301}",
+                &["e\u{301}", "t\u{301}", "e\u{301}"],
+            ),
+            ("\r\n\n", &["\r\n", "\n"]),
+            //flags are pairs of regional indicators
+            ("🇷🇺🇺🇸🇫", &["🇷🇺", "🇺🇸", "🇫"]),
+            //family joined with zero width joiners, skin tone modifier
+            ("👨‍👩‍👧👍🏽!", &["👨‍👩‍👧", "👍🏽", "!"]),
+            //hangul syllable from jamo and precomposed one
+            (
+                "\u{1100}\u{1161}\u{11a8}한",
+                &["\u{1100}\u{1161}\u{11a8}", "한"],
+            ),
+            //devanagari conjunct
+            ("क्षि", &["क्षि"]),
+            ("", &[]),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(&graphemes(text), expected, "{text:?}");
+        }
+    }
+}
@@ -1,95 +1,261 @@
-use std::{
-    error::Error,
-    fs::File,
-    io::{Read, Write},
-};
+use std::{collections::BTreeMap, error::Error, fs::File, io::Write, path::Path};
 
 use rand::{thread_rng, Rng};
 
 use crate::{
     algorithms::{
+        analysis,
+        fingerprint::Fingerprint,
+        generation::Strong,
         permutation::SimplePermutation,
         rail_fence::RailFenceCypher,
-        stacked::{Algorithm, EncryptionStyle, PadApproach, StackedCypher},
+        stacked::{
+            Algorithm, EncryptionStyle, GroupRemainder, Layer, PadApproach, PadValue, StackedCypher,
+        },
+        strength::{self, WarningLevel},
         vertical::VerticalPermutation,
     },
-    database::Database,
+    database::{self, Database, Entry, Loaded, LockMode, SaveOptions},
+    envelope::Envelope,
+    exchange,
+    journal::Change,
+    metadata::{self, Date, Metadata},
+    sharing::{self, Share},
     ExecResult,
 };
 
 use super::parse::{
-    AlgorithmType, DataSource, DataTarget, DecryptSource, PermutationType, PickApproach, Stmt,
+    AlgorithmDescription, AlgorithmType, DataSource, DataTarget, DecryptSource, KeyDefinition,
+    KeyName, LayerEdit, PermutationType, PickApproach, Stmt,
 };
 
+const DEFAULT_MAX_BLOCK_SIZE: usize = 64;
+
+///alias of the database opened with DATABASE, unless another one is given
+const MAIN_ALIAS: &str = "main";
+
+const DATABASE_REQUIRED: &str = "database required to use this. load one with DATABASE first";
+
 pub struct Interpreter {
     db: Option<Database>,
+    ///name of `db` in qualified key names
+    alias: String,
+    ///databases opened with ATTACH, by alias
+    attached: BTreeMap<String, Database>,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        Interpreter { db: None }
+        Interpreter {
+            db: None,
+            alias: MAIN_ALIAS.to_string(),
+            attached: BTreeMap::new(),
+        }
     }
 
     fn require_database(&mut self) -> Result<&mut Database, Box<dyn Error>> {
-        if self.db.is_none() {
-            Err("database required to use this. load one with DATABASE first".into())
-        } else {
-            Ok(self.db.as_mut().unwrap())
+        self.db.as_mut().ok_or_else(|| DATABASE_REQUIRED.into())
+    }
+
+    ///main database when `alias` is not given
+    fn database(&mut self, alias: Option<&str>) -> Result<&mut Database, Box<dyn Error>> {
+        match alias {
+            Some(alias) if alias != self.alias => self
+                .attached
+                .get_mut(alias)
+                .ok_or_else(|| format!("no database is attached as {alias}").into()),
+            _ => self.require_database(),
+        }
+    }
+
+    ///same as `database`, for reading keys of several databases at once
+    fn lookup(&self, alias: Option<&str>) -> Result<&Database, Box<dyn Error>> {
+        match alias {
+            Some(alias) if alias != self.alias => self
+                .attached
+                .get(alias)
+                .ok_or_else(|| format!("no database is attached as {alias}").into()),
+            _ => self.db.as_ref().ok_or_else(|| DATABASE_REQUIRED.into()),
         }
     }
 
+    ///alias of the attached database stored in the file
+    fn attached_as(&self, name: &str) -> Option<&str> {
+        let path = std::fs::canonicalize(name).ok()?;
+        self.attached
+            .iter()
+            .find(|(_, db)| std::fs::canonicalize(db.path()).ok().as_ref() == Some(&path))
+            .map(|(alias, _)| alias.as_str())
+    }
+
     pub fn visit_stmt(&mut self, stmt: &Stmt) -> Result<ExecResult, Box<dyn Error>> {
         match stmt {
-            Stmt::DatabasePick { name, create } => {
-                let mut options = std::fs::File::options();
-                let mut file = options.read(true).write(true);
+            Stmt::DatabasePick {
+                name,
+                create,
+                shared,
+                password,
+                alias,
+            } => {
+                let alias = alias.as_deref().unwrap_or(MAIN_ALIAS);
+                if self.attached.contains_key(alias) {
+                    return Err(
+                        format!("{alias} is already the alias of an attached database").into(),
+                    );
+                }
+
+                open_file(name, create)?;
+                if let Some(attached) = self.attached_as(name) {
+                    return Err(format!("{name} is already attached as {attached}").into());
+                }
 
-                match create {
-                    PickApproach::Create => {
-                        file = file.create_new(true);
+                let replacing = self.db.is_some();
+                //own lock on the same file would get in the way, so it is
+                //released while opening. If opening fails, the database and its
+                //unsaved changes are kept
+                let mut reopened = None;
+                if self.db.as_ref().is_some_and(|db| {
+                    std::fs::canonicalize(db.path()).ok() == std::fs::canonicalize(name).ok()
+                }) {
+                    if let Some(db) = self.db.as_mut() {
+                        db.release_lock()?;
                     }
-                    PickApproach::Load => file = file.create(false),
-                    PickApproach::Any => file = file.create(true),
+                    reopened = self.db.take();
                 }
 
-                let file = file
-                    .open(name)
-                    .map_err(|e| format!("failed to open database file: {e}"))?;
-                let database = Database::load_from_file(file)
-                    .map_err(|e| format!("failed to read database: {e}"))?;
+                let (database, loaded) = match open_database(name, *shared, password) {
+                    Ok(opened) => opened,
+                    Err(e) => {
+                        if let Some(mut db) = reopened {
+                            let relocked = db.lock_again();
+                            self.db = Some(db);
+                            if let Err(lock_error) = relocked {
+                                return Err(format!(
+                                    "{e}, and the database could not be locked again: {lock_error}"
+                                )
+                                .into());
+                            }
+                        }
+                        return Err(e);
+                    }
+                };
 
-                let message = if self.db.is_some() {
+                let mut message = if replacing {
                     format!("replaced own database with {}", name)
                 } else {
                     format!("loaded database {}", name)
                 };
+                if let Loaded::Backup { error } = loaded {
+                    message += &recovered_message(&database, &error);
+                }
                 self.db = Some(database);
+                self.alias = alias.to_string();
                 Ok(ExecResult::Message(message))
             }
 
-            Stmt::Save => {
-                self.require_database()?;
-                self.db.as_mut().unwrap().save()?;
-                Ok(ExecResult::Message("saved database".to_string()))
+            Stmt::Attach {
+                name,
+                alias,
+                shared,
+                password,
+            } => {
+                if *alias == self.alias || self.attached.contains_key(alias) {
+                    return Err(format!("{alias} is already the alias of a database").into());
+                }
+                open_file(name, &PickApproach::Any)?;
+                if let Some(attached) = self.attached_as(name) {
+                    return Err(format!("{name} is already attached as {attached}").into());
+                }
+                if self.db.as_ref().is_some_and(|db| {
+                    std::fs::canonicalize(db.path()).ok() == std::fs::canonicalize(name).ok()
+                }) {
+                    return Err(format!("{name} is already open as {}", self.alias).into());
+                }
+
+                let (database, loaded) = open_database(name, *shared, password)?;
+                let mut message = format!("attached database {name} as {alias}");
+                if let Loaded::Backup { error } = loaded {
+                    message += &recovered_message(&database, &error);
+                }
+                self.attached.insert(alias.clone(), database);
+                Ok(ExecResult::Message(message))
+            }
+
+            Stmt::Detach(alias) => {
+                let database = self
+                    .attached
+                    .remove(alias)
+                    .ok_or_else(|| format!("no database is attached as {alias}"))?;
+                let unsaved = database.unsaved_changes().len();
+                Ok(ExecResult::Message(if unsaved > 0 {
+                    format!("detached {alias}, {unsaved} unsaved changes were discarded")
+                } else {
+                    format!("detached {alias}")
+                }))
             }
 
-            Stmt::Reload => {
-                self.require_database()?.reload()?;
-                Ok(ExecResult::Message("reloaded database".to_string()))
+            Stmt::Save {
+                database,
+                compact,
+                backup,
+                merge,
+            } => {
+                let merged = self.database(database.as_deref())?.save(SaveOptions {
+                    compact: *compact,
+                    backup: *backup,
+                    merge: *merge,
+                })?;
+                Ok(ExecResult::Message(if merged {
+                    "saved database together with changes made by others".to_string()
+                } else {
+                    "saved database".to_string()
+                }))
+            }
+
+            Stmt::ChangePassword { database, password } => {
+                let database = self.database(database.as_deref())?;
+                database.change_password(password.clone());
+                Ok(ExecResult::Message(
+                    if database.is_protected() {
+                        "password changed, SAVE to encrypt the database with it"
+                    } else {
+                        "password removed, SAVE to store the database unencrypted"
+                    }
+                    .to_string(),
+                ))
+            }
+
+            Stmt::Reload(database) => {
+                let database = self.database(database.as_deref())?;
+                let message = match database.reload()? {
+                    Loaded::Main => "reloaded database".to_string(),
+                    Loaded::Backup { error } => {
+                        "reloaded database".to_string() + &recovered_message(database, &error)
+                    }
+                };
+                Ok(ExecResult::Message(message))
             }
 
             Stmt::Exit => Ok(ExecResult::Exit),
 
-            Stmt::List => {
-                self.require_database()?;
+            Stmt::List { database, tag } => {
+                let today = Date::today();
 
                 let items = self
-                    .db
-                    .as_ref()
-                    .unwrap()
+                    .lookup(database.as_deref())?
                     .get_inner()
-                    .keys()
-                    .map(|k| k.to_string())
+                    .iter()
+                    .filter(|(_, v)| tag.as_ref().is_none_or(|t| v.metadata.has_tag(t)))
+                    .map(|(k, v)| {
+                        let mut item = format!("{k} {}", Fingerprint::of(&v.cypher));
+                        if !v.metadata.tags.is_empty() {
+                            item += &format!(" [{}]", v.metadata.tags.join(", "));
+                        }
+                        if v.metadata.is_expired(today) {
+                            item += " (expired)";
+                        }
+                        item
+                    })
                     .collect::<Vec<_>>();
                 let total = items.len();
 
@@ -100,30 +266,58 @@ impl Interpreter {
             }
 
             Stmt::Describe(name) => Ok({
-                let cypher: &StackedCypher = self
-                    .require_database()?
-                    .get(name)
+                let entry: &Entry = self
+                    .lookup(name.database.as_deref())?
+                    .get_entry(&name.name)
                     .ok_or_else(|| format!("no such entry {name}"))?;
 
-                let items = cypher.items();
+                let items = entry.cypher.items();
 
                 let items = items
-                    .map(|(pad, style, algo)| {
-                        let pad = match pad {
-                            PadApproach::Padding => "padding",
-                            PadApproach::Unpadding => "unpadding",
+                    .enumerate()
+                    .map(|(i, layer)| {
+                        let pad = match (layer.pad_approach, layer.pad_value) {
+                            (PadApproach::Padding, PadValue::Default) => "padding".to_string(),
+                            (PadApproach::Padding, PadValue::Fixed(code)) => {
+                                match char::from_u32(code).filter(|c| !c.is_control()) {
+                                    Some(c) => format!("padding(pad {:?})", c),
+                                    None => format!("padding(pad {})", code),
+                                }
+                            }
+                            (PadApproach::Padding, PadValue::Random) => {
+                                "padding(pad random)".to_string()
+                            }
+                            (PadApproach::Padding, PadValue::Frequency) => {
+                                "padding(pad frequency)".to_string()
+                            }
+                            (PadApproach::Padding, PadValue::Delimited) => {
+                                "padding(delimited)".to_string()
+                            }
+                            (PadApproach::Unpadding, _) => "unpadding".to_string(),
+                            (PadApproach::Stealing, _) => "stealing".to_string(),
                         };
 
-                        let style = match style {
+                        let style = match layer.style {
                             EncryptionStyle::Bit => "bit".to_string(),
+                            EncryptionStyle::Bits(w) => format!("bits({})", w),
                             EncryptionStyle::Byte => "byte".to_string(),
                             EncryptionStyle::Char => "char".to_string(),
-                            EncryptionStyle::Group(g) => format!("group({})", g),
+                            EncryptionStyle::Group(g, remainder) => match remainder {
+                                GroupRemainder::Reject => format!("group({})", g),
+                                GroupRemainder::Pad(filler) => {
+                                    format!("group({}, pad {:?})", g, filler)
+                                }
+                                GroupRemainder::Bypass => format!("group({}, bypass)", g),
+                                GroupRemainder::Record => format!("group({}, record)", g),
+                            },
+                            EncryptionStyle::Word => "word".to_string(),
+                            EncryptionStyle::Line => "line".to_string(),
+                            EncryptionStyle::Grapheme => "grapheme".to_string(),
                         };
 
-                        let algo = match algo {
+                        let algo = match &layer.algorithm {
                             Algorithm::Permutation(p) => {
-                                format!("{:?}", p.get_inner())
+                                format!("{:?} = {}", p.get_inner(), p.get_inner())
                             }
                             Algorithm::RailFence(r) => {
                                 format!("{:?}", r.get_inner())
@@ -133,17 +327,58 @@ impl Interpreter {
                             }
                         };
 
-                        format!("{pad} {style} {algo}")
+                        format!("layer {i}: {pad} {style} {algo}")
                     })
                     .collect::<Vec<_>>()
                     .join("; ");
 
-                ExecResult::Message(format!("algorithms: [{items}]"))
+                let mut message = format!(
+                    "algorithms (layers numbered from 0): [{items}]\nversion: {}",
+                    entry.version
+                );
+                message += &describe_metadata(&entry.metadata);
+                ExecResult::Message(message)
             }),
-            Stmt::Encrypt { from, key, to } => {
-                let db = self.require_database()?;
+            Stmt::Strength(name) => {
+                let cypher = self
+                    .lookup(name.database.as_deref())?
+                    .get(&name.name)
+                    .ok_or_else(|| format!("no such entry {name}"))?;
+
+                let report = strength::analyze(cypher);
 
-                let key = db.get(key).ok_or_else(|| format!("no key {key}"))?;
+                let mut lines = report
+                    .layers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, layer)| {
+                        let order = layer
+                            .order
+                            .map_or_else(|| "more than 2^128".to_string(), |o| o.to_string());
+                        let mut line = format!(
+                            "layer {i}: {:.1} bits, block size {}, {} fixed point(s), order {order}",
+                            layer.keyspace_bits, layer.block_size, layer.fixed_points
+                        );
+                        for warning in &layer.warnings {
+                            line.push_str(&format!("\n  warning: {warning}"));
+                        }
+                        line
+                    })
+                    .collect::<Vec<_>>();
+
+                lines.extend(report.warnings.iter().map(|w| format!("warning: {w}")));
+                lines.push(format!(
+                    "effective keyspace: {:.1} bits, warning level: {}",
+                    report.effective_bits, report.level
+                ));
+
+                Ok(ExecResult::Message(lines.join("\n")))
+            }
+
+            Stmt::Encrypt { from, key, to } => {
+                let entry = encryption_key(self.lookup(key.database.as_deref())?, key)?;
+                let (key, version) = (&entry.cypher, entry.version);
+                let fingerprint = Fingerprint::of(key);
 
                 let data = match from {
                     DataSource::String(s) => s.clone().into_bytes(),
@@ -154,59 +389,99 @@ impl Interpreter {
 
                 match to {
                     DataTarget::Console => Ok(ExecResult::Message(format!(
-                        "{sizes:?} {msg:?} (\"{}\")",
+                        "{sizes:?} {msg:?} (\"{}\") FINGERPRINT \"{fingerprint}\" (key version {version})",
                         escape(&String::from_utf8_lossy(&msg))
                     ))),
                     DataTarget::File(f) => {
-                        let mut file = File::options().write(true).create(true).open(f)?;
+                        let mut file = File::options()
+                            .write(true)
+                            .create(true)
+                            .truncate(true)
+                            .open(f)?;
 
-                        file.write_all(&sizes.len().to_be_bytes())?;
-
-                        for size in sizes {
-                            file.write_all(&size.to_be_bytes())?;
+                        Envelope {
+                            fingerprint: Some(fingerprint),
+                            key_version: Some(version),
+                            sizes,
+                            data: msg,
                         }
-                        file.write_all(msg.as_slice())?;
+                        .write_to(&mut file)?;
                         Ok(ExecResult::Message(format!("written {f}")))
                     }
                 }
             }
-            Stmt::Decrypt { from, key, to } => {
-                let db = self.require_database()?;
-
-                let key = db.get(key).ok_or_else(|| format!("no key {key}"))?;
+            Stmt::Decrypt {
+                from,
+                key: key_name,
+                fingerprint,
+                to,
+            } => {
+                let db = self.lookup(key_name.database.as_deref())?;
 
-                let data = match from {
-                    DecryptSource::ConsoleString(sizes, s) => {
-                        (sizes.clone(), unescape(s)?.into_bytes())
-                    }
+                let envelope = match from {
+                    DecryptSource::ConsoleString(sizes, s) => Envelope {
+                        fingerprint: None,
+                        key_version: None,
+                        sizes: sizes.clone().unwrap_or_default(),
+                        data: unescape(s)?.into_bytes(),
+                    },
 
-                    DecryptSource::ConsoleRaw(sizes, data) => (sizes.clone(), data.clone()),
+                    DecryptSource::ConsoleRaw(sizes, data) => Envelope {
+                        fingerprint: None,
+                        key_version: None,
+                        sizes: sizes.clone(),
+                        data: data.clone(),
+                    },
 
                     DecryptSource::File(f) => {
                         let mut file = File::options().read(true).open(f)?;
+                        Envelope::read_from(&mut file)?
+                    }
+                };
+
+                let expected = match fingerprint {
+                    Some(f) => Some(Fingerprint::from_hex(f)?),
+                    None => envelope.fingerprint,
+                };
 
-                        let mut buf = [0u8; std::mem::size_of::<usize>()];
-                        file.read_exact(&mut buf)?;
+                let (key, older) = pick_version(db, key_name, envelope.key_version, expected)?;
+                let key_name = &match older {
+                    Some(version) => format!("{key_name}@{version}"),
+                    None => key_name.to_string(),
+                };
 
-                        let total_indices = usize::from_be_bytes(buf);
+                if let DecryptSource::ConsoleString(None, _) = from {
+                    if key.requires_sizes() {
+                        return Err(format!(
+                            "key \"{key_name}\" does not delimit its padding, sizes are needed: DECRYPT [sizes] \"message\""
+                        )
+                        .into());
+                    }
+                }
 
-                        let mut sizes = vec![];
+                let warning = expected
+                    .filter(|expected| *expected != Fingerprint::of(key))
+                    .map(|expected| {
+                        format!(
+                            "warning: message was encrypted with key {expected}, but \"{key_name}\" is {}",
+                            Fingerprint::of(key)
+                        )
+                    });
 
-                        for _ in 0..total_indices {
-                            let mut buf = [0u8; std::mem::size_of::<usize>()];
-                            file.read_exact(&mut buf)?;
-                            sizes.push(usize::from_be_bytes(buf));
-                        }
-                        let mut data = vec![];
-                        file.read_to_end(&mut data)?;
-                        (sizes, data)
+                let decrypted = match from {
+                    DecryptSource::ConsoleString(None, _) => {
+                        key.decrypt_without_sizes(envelope.data)
                     }
+                    _ => key.decrypt((envelope.sizes, envelope.data)),
                 };
 
-                let message = key.decrypt(data)?;
+                let message = decrypted.map_err(|e| match &warning {
+                    Some(warning) => format!("{e} ({warning})").into(),
+                    None => e,
+                })?;
 
-                match to {
-                    DataTarget::Console => Ok(ExecResult::Message(format!("message: {}", {
+                let result = match to {
+                    DataTarget::Console => format!("message: {}", {
                         if let Ok(msg) = String::from_utf8(message.clone()) {
                             format!("\"{}\"", msg)
                         } else {
@@ -215,74 +490,770 @@ impl Interpreter {
                                 escape(&String::from_utf8_lossy(&message))
                             )
                         }
-                    }))),
+                    }),
                     DataTarget::File(f) => {
-                        let mut file = File::options().write(true).create(true).open(f)?;
+                        let mut file = File::options()
+                            .write(true)
+                            .create(true)
+                            .truncate(true)
+                            .open(f)?;
                         file.write_all(message.as_slice())?;
-                        Ok(ExecResult::Message(format!("written {f}")))
+                        format!("written {f}")
                     }
+                };
+
+                let result = match older {
+                    Some(_) => format!("decrypted with older version \"{key_name}\"\n{result}"),
+                    None => result,
+                };
+                Ok(ExecResult::Message(match warning {
+                    Some(warning) => format!("{warning}\n{result}"),
+                    None => result,
+                }))
+            }
+
+            Stmt::Fingerprint(name) => {
+                let cypher = self
+                    .lookup(name.database.as_deref())?
+                    .get(&name.name)
+                    .ok_or_else(|| format!("no such entry {name}"))?;
+                let fingerprint = Fingerprint::of(cypher);
+
+                Ok(ExecResult::Message(format!(
+                    "fingerprint of \"{name}\": {fingerprint} ({})",
+                    fingerprint.to_words()
+                )))
+            }
+
+            Stmt::Undo(database) => {
+                let changes = self.database(database.as_deref())?.undo()?;
+                Ok(ExecResult::Message(format!(
+                    "undone: {}",
+                    describe_changes(&changes)
+                )))
+            }
+
+            Stmt::Redo(database) => {
+                let changes = self.database(database.as_deref())?.redo()?;
+                Ok(ExecResult::Message(format!(
+                    "redone: {}",
+                    describe_changes(&changes)
+                )))
+            }
+
+            Stmt::Begin(database) => {
+                self.database(database.as_deref())?.begin()?;
+                Ok(ExecResult::Message("transaction started".to_string()))
+            }
+
+            Stmt::Commit(database) => {
+                let count = self.database(database.as_deref())?.commit()?;
+                Ok(ExecResult::Message(format!(
+                    "committed {count} changes, UNDO reverts them together"
+                )))
+            }
+
+            Stmt::Rollback(database) => {
+                let changes = self.database(database.as_deref())?.rollback()?;
+                Ok(ExecResult::Message(if changes.is_empty() {
+                    "rolled back, nothing was changed".to_string()
+                } else {
+                    format!("rolled back: {}", describe_changes(&changes))
+                }))
+            }
+
+            Stmt::Status(database) => {
+                let database = self.database(database.as_deref())?;
+                let changes = database.unsaved_changes();
+                let journal = database.journal();
+
+                let mut lines = if changes.is_empty() {
+                    vec!["no unsaved changes".to_string()]
+                } else {
+                    std::iter::once("unsaved changes:".to_string())
+                        .chain(changes.iter().map(ToString::to_string))
+                        .chain(std::iter::once(format!("({} total)", changes.len())))
+                        .collect()
+                };
+                if journal.in_transaction() {
+                    lines.push(format!(
+                        "transaction is open with {} changes",
+                        journal.pending()
+                    ));
                 }
+                lines.push(format!(
+                    "{} steps to undo, {} to redo",
+                    journal.undo_steps(),
+                    journal.redo_steps()
+                ));
+                Ok(ExecResult::Message(lines.join("\n")))
             }
 
-            Stmt::Delete(n) => match self.require_database()?.delete(n) {
+            Stmt::Delete(n) => match self.database(n.database.as_deref())?.delete(&n.name) {
                 Some(_) => Ok(ExecResult::Message(format!("deleted key {n}"))),
                 None => Err("no such key".into()),
             },
 
-            Stmt::Add { name, algos } => {
-                let db = self.require_database()?;
+            Stmt::History(name) => {
+                let entry = self
+                    .lookup(name.database.as_deref())?
+                    .get_entry(&name.name)
+                    .ok_or_else(|| format!("no such entry {name}"))?;
 
-                let cypher = {
-                    let mut cypher = StackedCypher::new();
+                let mut lines = entry
+                    .history
+                    .iter()
+                    .map(|revision| {
+                        format!(
+                            "{name}@{} {} retired {}",
+                            revision.version,
+                            Fingerprint::of(&revision.cypher),
+                            metadata::format_timestamp(revision.retired)
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                lines.push(format!(
+                    "{name}@{} {} current",
+                    entry.version,
+                    Fingerprint::of(&entry.cypher)
+                ));
+                Ok(ExecResult::Message(lines.join("\n")))
+            }
 
-                    for algo in algos {
-                        let pad = algo.padding;
-                        let style = algo.style;
-                        let algo: Algorithm = match &algo.algo_type {
-                            AlgorithmType::Permutation(PermutationType::Generated(size)) => {
-                                SimplePermutation::random_with_size(*size)?.into()
-                            }
+            Stmt::Prune { name, keep } => {
+                let removed = self
+                    .database(name.database.as_deref())?
+                    .prune(&name.name, *keep)?;
+                Ok(ExecResult::Message(format!(
+                    "removed {removed} old version(s) of \"{name}\""
+                )))
+            }
 
-                            AlgorithmType::Permutation(PermutationType::Manual(config)) => {
-                                SimplePermutation::try_from(config.clone())
-                                    .ok_or_else(|| {
-                                        <Box<dyn Error>>::from("misconfigured permutation")
-                                    })?
-                                    .into()
-                            }
+            Stmt::Rotate {
+                from,
+                to,
+                directory,
+                dry_run,
+            } => {
+                let db = self.lookup(from.database.as_deref())?;
+                db.get(&from.name).ok_or_else(|| format!("no key {from}"))?;
+                let target = encryption_key(self.lookup(to.database.as_deref())?, to)?;
 
-                            AlgorithmType::RailFence(None) => {
-                                thread_rng().gen::<RailFenceCypher>().into()
-                            }
+                let mut files = std::fs::read_dir(directory)
+                    .map_err(|e| format!("failed to read directory {directory}: {e}"))?
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                //temporary files are left over from interrupted rotations
+                files.retain(|path| path.is_file() && path.extension() != Some("tmp".as_ref()));
+                files.sort();
 
-                            AlgorithmType::RailFence(Some((rows, columns))) => {
-                                RailFenceCypher::try_new(*rows, *columns)?.into()
-                            }
-                            AlgorithmType::Vertical(None) => {
-                                thread_rng().gen::<VerticalPermutation>().into()
-                            }
-                            AlgorithmType::Vertical(Some((rows, columns, c))) => {
-                                let permutation = SimplePermutation::try_from(c.clone())
-                                    .ok_or_else(|| {
-                                        <Box<dyn Error>>::from(
-                                            "misconfigured permutation of vertical cypher",
-                                        )
-                                    })?;
-                                VerticalPermutation::try_new(*rows, *columns, permutation)?.into()
-                            }
-                        };
-                        cypher.push(pad, style, algo);
+                let (mut rotated, mut skipped, mut failed) = (0, 0, 0);
+                let mut lines = vec![];
+                for path in &files {
+                    let file = path.display();
+                    match rotate_file(db, path, from, target, *dry_run) {
+                        Ok(true) => {
+                            rotated += 1;
+                            lines.push(format!("rotated {file}"));
+                        }
+                        Ok(false) => {
+                            skipped += 1;
+                            lines.push(format!("skipped {file}: already encrypted with \"{to}\""));
+                        }
+                        Err(e) => {
+                            failed += 1;
+                            lines.push(format!("failed {file}: {e}"));
+                        }
+                    }
+                }
+                if rotated > 0 && !dry_run {
+                    database::sync_directory(&files[0])?;
+                }
+
+                lines.push(format!(
+                    "{rotated} rotated, {skipped} skipped, {failed} failed{}",
+                    if *dry_run {
+                        " (dry run, no files were changed)"
+                    } else {
+                        ""
+                    }
+                ));
+                Ok(ExecResult::Message(lines.join("\n")))
+            }
+
+            Stmt::Copy { from, to } => {
+                check_name(&to.name)?;
+                let source = self
+                    .lookup(from.database.as_deref())?
+                    .get_entry(&from.name)
+                    .ok_or_else(|| format!("no such entry {from}"))?;
+                let (cypher, metadata) = (source.cypher.clone(), source.metadata.clone());
+
+                let db = self.database(to.database.as_deref())?;
+                let entry = new_entry(db, &to.name, cypher, &metadata);
+                let version = entry.version;
+                Ok(ExecResult::Message(match db.add(&to.name, entry) {
+                    Some(_) => format!("copied \"{from}\" to \"{to}\", now version {version}"),
+                    None => format!("copied \"{from}\" to \"{to}\""),
+                }))
+            }
+
+            Stmt::Rename { from, to } => {
+                if from.database.as_deref().unwrap_or(&self.alias)
+                    != to.database.as_deref().unwrap_or(&self.alias)
+                {
+                    return Err("RENAME works within one database, COPY the key instead".into());
+                }
+                check_name(&to.name)?;
+                if database::split_version(&from.name).is_some() {
+                    return Err(format!("\"{from}\" is a version, COPY it instead").into());
+                }
+                self.database(from.database.as_deref())?
+                    .rename(&from.name, &to.name)?;
+                Ok(ExecResult::Message(format!(
+                    "renamed \"{from}\" to \"{to}\""
+                )))
+            }
+
+            Stmt::EditLayers { name, edit } => {
+                let db = self.database(name.database.as_deref())?;
+                let entry = db
+                    .get_entry(&name.name)
+                    .ok_or_else(|| format!("no such entry {name}"))?;
+                let mut cypher = entry.cypher.clone();
+                let metadata = entry.metadata.clone();
+
+                let done = match edit {
+                    LayerEdit::Insert(i, algo) => {
+                        cypher.insert_layer(*i, build_layer(algo)?)?;
+                        format!("inserted layer {i} into")
+                    }
+                    LayerEdit::Remove(i) => {
+                        cypher.remove_layer(*i)?;
+                        format!("removed layer {i} from")
+                    }
+                    LayerEdit::Replace(i, algo) => {
+                        cypher.replace_layer(*i, build_layer(algo)?)?;
+                        format!("replaced layer {i} of")
+                    }
+                    LayerEdit::Swap(i, j) => {
+                        cypher.swap_layers(*i, *j)?;
+                        format!("swapped layers {i} and {j} of")
                     }
-                    cypher
                 };
+                if cypher == entry.cypher {
+                    return Err(format!("the edit does not change \"{name}\"").into());
+                }
+
+                let level = strength::analyze(&cypher).level;
+                let entry = new_entry(db, &name.name, cypher, &metadata);
+                let version = entry.version;
+                db.add(&name.name, entry);
+                Ok(ExecResult::Message(weak_key_warning(
+                    format!("{done} \"{name}\", now version {version}"),
+                    name,
+                    level,
+                )))
+            }
+
+            Stmt::Split {
+                name,
+                count,
+                threshold,
+                directory,
+            } => {
+                let cypher = self
+                    .lookup(name.database.as_deref())?
+                    .get(&name.name)
+                    .ok_or_else(|| format!("no such entry {name}"))?;
+                let shares = sharing::split(cypher, *count, *threshold)?;
+
+                let directory = Path::new(directory.as_deref().unwrap_or("."));
+                let stem = name
+                    .name
+                    .chars()
+                    .map(|c| {
+                        if c.is_alphanumeric() || c == '-' {
+                            c
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect::<String>();
+                let paths = shares
+                    .iter()
+                    .map(|share| directory.join(format!("{stem}.{}.share", share.index)))
+                    .collect::<Vec<_>>();
+                //shares of another split must not be mixed with these
+                if let Some(path) = paths.iter().find(|path| path.exists()) {
+                    return Err(format!("{} already exists", path.display()).into());
+                }
+
+                let mut lines = vec![];
+                for (share, path) in shares.iter().zip(&paths) {
+                    File::options()
+                        .write(true)
+                        .create_new(true)
+                        .open(path)?
+                        .write_all(&share.to_bytes())?;
+                    lines.push(format!(
+                        "wrote share {} of {count} into {}",
+                        share.index,
+                        path.display()
+                    ));
+                }
+                lines.push(format!(
+                    "any {threshold} of {count} shares rebuild \"{name}\" with COMBINE"
+                ));
+                Ok(ExecResult::Message(lines.join("\n")))
+            }
+
+            Stmt::Combine { from, name } => {
+                check_name(&name.name)?;
+                let shares = from
+                    .iter()
+                    .map(|path| {
+                        std::fs::read(path)
+                            .map_err(Box::<dyn Error>::from)
+                            .and_then(|bytes| Share::from_bytes(&bytes))
+                            .map_err(|e| format!("{path}: {e}"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let cypher = sharing::combine(&shares)?;
 
-                Ok(ExecResult::Message(match db.add(name, cypher) {
-                    Some(_) => format!("replaced cypher \"{}\"", name),
-                    None => format!("added cypher \"{}\"", name),
+                let db = self.database(name.database.as_deref())?;
+                let entry = new_entry(db, &name.name, cypher, &Metadata::default());
+                let version = entry.version;
+                Ok(ExecResult::Message(match db.add(&name.name, entry) {
+                    Some(_) => format!(
+                        "combined \"{name}\" from {} shares, now version {version}",
+                        shares.len()
+                    ),
+                    None => format!("combined \"{name}\" from {} shares", shares.len()),
                 }))
             }
+
+            Stmt::Export { name, to } => {
+                let db = self.lookup(name.as_ref().and_then(|n| n.database.as_deref()))?;
+                let mut keys = match name {
+                    Some(name) => {
+                        let entry = db
+                            .get_entry(&name.name)
+                            .ok_or_else(|| format!("no such entry {name}"))?;
+                        vec![(name.name.as_str(), entry)]
+                    }
+                    None => db
+                        .get_inner()
+                        .iter()
+                        .map(|(name, entry)| (name.as_str(), entry))
+                        .collect(),
+                };
+                keys.sort_by_key(|(name, _)| *name);
+
+                let total = keys.len();
+                exchange::export(
+                    Path::new(to),
+                    keys.into_iter()
+                        .map(|(name, entry)| (name, &entry.cypher, &entry.metadata)),
+                )?;
+                Ok(ExecResult::Message(format!(
+                    "exported {total} key(s) into {to}"
+                )))
+            }
+
+            Stmt::Import { from, name } => {
+                let mut keys = exchange::import(Path::new(from))?;
+                if let Some(name) = name {
+                    if keys.len() != 1 {
+                        return Err(format!(
+                            "AS renames a single key, but {from} has {} keys",
+                            keys.len()
+                        )
+                        .into());
+                    }
+                    keys[0].name = name.name.clone();
+                }
+                for key in &keys {
+                    check_name(&key.name)?;
+                }
+
+                let db = self.database(name.as_ref().and_then(|n| n.database.as_deref()))?;
+                //one step to UNDO, unless it is a part of a bigger transaction
+                let own_transaction = !db.journal().in_transaction();
+                if own_transaction {
+                    db.begin()?;
+                }
+                let mut lines = vec![];
+                for key in keys {
+                    if db.get_entry(&key.name).map(|e| &e.cypher) == Some(&key.cypher) {
+                        lines.push(format!("skipped \"{}\", the key is the same", key.name));
+                        continue;
+                    }
+                    let entry = new_entry(db, &key.name, key.cypher, &key.metadata);
+                    let version = entry.version;
+                    lines.push(match db.add(&key.name, entry) {
+                        Some(_) => {
+                            format!("replaced cypher \"{}\", now version {version}", key.name)
+                        }
+                        None => format!("added cypher \"{}\"", key.name),
+                    });
+                }
+                if own_transaction {
+                    db.commit()?;
+                }
+                Ok(ExecResult::Message(lines.join("\n")))
+            }
+
+            Stmt::Add {
+                name,
+                definition,
+                metadata,
+            } => {
+                check_name(&name.name)?;
+
+                let cypher = match definition {
+                    KeyDefinition::Algorithms(algos) => {
+                        let mut cypher = StackedCypher::new();
+
+                        for algo in algos {
+                            cypher.push_layer(build_layer(algo)?);
+                        }
+                        cypher
+                    }
+                    KeyDefinition::InverseOf(source) => self
+                        .lookup(source.database.as_deref())?
+                        .get(&source.name)
+                        .ok_or_else(|| format!("no key {source}"))?
+                        .inverse(),
+                    KeyDefinition::Then(sources) | KeyDefinition::Concat(sources) => sources
+                        .iter()
+                        .map(|source| -> Result<_, Box<dyn Error>> {
+                            self.lookup(source.database.as_deref())?
+                                .get(&source.name)
+                                .ok_or_else(|| format!("no key {source}").into())
+                        })
+                        .try_fold(StackedCypher::new(), |cypher, next| {
+                            next.map(|next| match definition {
+                                KeyDefinition::Then(_) => cypher.then(next),
+                                _ => cypher.concat(next),
+                            })
+                        })?,
+                };
+
+                let level = strength::analyze(&cypher).level;
+
+                let db = self.database(name.database.as_deref())?;
+                let entry = new_entry(db, &name.name, cypher, metadata);
+                let version = entry.version;
+
+                let message = match db.add(&name.name, entry) {
+                    Some(_) => format!("replaced cypher \"{name}\", now version {version}"),
+                    None => format!("added cypher \"{name}\""),
+                };
+
+                Ok(ExecResult::Message(weak_key_warning(message, name, level)))
+            }
+
+            Stmt::GuessBlockSize { from, max_size } => {
+                let data = match from {
+                    DataSource::String(s) => s.clone().into_bytes(),
+                    DataSource::File(f) => std::fs::read(f)?,
+                };
+
+                //analyze characters when possible, as most keys work with text
+                let candidates = match String::from_utf8(data) {
+                    Ok(s) => {
+                        let chars = s.chars().collect::<Vec<_>>();
+                        let max_size = max_size
+                            .unwrap_or(DEFAULT_MAX_BLOCK_SIZE)
+                            .min(analysis::max_testable_block_size(chars.len()));
+                        analysis::rank_block_sizes(&chars, max_size)
+                    }
+                    Err(e) => {
+                        let bytes = e.into_bytes();
+                        let max_size = max_size
+                            .unwrap_or(DEFAULT_MAX_BLOCK_SIZE)
+                            .min(analysis::max_testable_block_size(bytes.len()));
+                        analysis::rank_block_sizes(&bytes, max_size)
+                    }
+                };
+
+                let guess = analysis::pick_block_size(&candidates)
+                    .ok_or("not enough data to guess block size")?;
+
+                let top = candidates
+                    .iter()
+                    .take(5)
+                    .map(|c| format!("{} ({:.3})", c.block_size, c.score))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                Ok(ExecResult::Message(format!(
+                    "most likely block size: {guess}\ncandidates: {top}"
+                )))
+            }
+        }
+    }
+}
+
+///`message` with a hint to look at STRENGTH when the key is weak
+fn weak_key_warning(message: String, name: &KeyName, level: WarningLevel) -> String {
+    if level < WarningLevel::High {
+        return message;
+    }
+    let alias = name.database.as_ref().map(|a| a.clone() + ".");
+    format!(
+        "{message} (warning: weak key, see STRENGTH {}\"{}\")",
+        alias.unwrap_or_default(),
+        name.name
+    )
+}
+
+fn check_name(name: &str) -> Result<(), Box<dyn Error>> {
+    if database::split_version(name).is_some() {
+        return Err(format!(
+            "\"{name}\" looks like a version of a key, names cannot end with @ and a number"
+        )
+        .into());
+    }
+    Ok(())
+}
+
+///entry to add under `name` with the given note, tags and expiry. Replacing
+///keeps the owner and creation time, the replaced key goes to history
+fn new_entry(db: &Database, name: &str, cypher: StackedCypher, metadata: &Metadata) -> Entry {
+    let now = metadata::now();
+    let replaced = db.get_entry(name);
+    let metadata = Metadata {
+        owner: replaced.map_or_else(metadata::current_user, |e| e.metadata.owner.clone()),
+        created: replaced.map_or(Some(now), |e| e.metadata.created),
+        modified: Some(now),
+        ..metadata.clone()
+    };
+    match replaced {
+        Some(replaced) => replaced.replaced_with(cypher, metadata, now),
+        None => Entry::new(cypher, metadata),
+    }
+}
+
+///decrypts an encrypted file with `from` and replaces it with the message
+///encrypted by `to`. Returns false if the file is already encrypted with `to`
+fn rotate_file(
+    db: &Database,
+    path: &Path,
+    from: &KeyName,
+    to: &Entry,
+    dry_run: bool,
+) -> Result<bool, Box<dyn Error>> {
+    let envelope = Envelope::read_from(&mut File::open(path)?)?;
+    let fingerprint = Fingerprint::of(&to.cypher);
+    if envelope.fingerprint == Some(fingerprint) {
+        return Ok(false);
+    }
+
+    let (key, _) = pick_version(db, from, envelope.key_version, envelope.fingerprint)?;
+    if let Some(expected) = envelope.fingerprint.filter(|f| *f != Fingerprint::of(key)) {
+        return Err(format!("encrypted with key {expected}, not with \"{from}\"").into());
+    }
+    let message = key.decrypt((envelope.sizes, envelope.data))?;
+    let (sizes, data) = to.cypher.encrypt(&message)?;
+
+    if !dry_run {
+        let mut content = vec![];
+        Envelope {
+            fingerprint: Some(fingerprint),
+            key_version: Some(to.version),
+            sizes,
+            data,
+        }
+        .write_to(&mut content)?;
+        database::replace_file(path, &content)?;
+    }
+    Ok(true)
+}
+
+///creates the file or checks that it exists, as asked
+fn open_file(name: &str, create: &PickApproach) -> Result<(), Box<dyn Error>> {
+    let mut options = std::fs::File::options();
+    let mut file = options.read(true).write(true);
+
+    match create {
+        PickApproach::Create => {
+            file = file.create_new(true);
+        }
+        PickApproach::Load => file = file.create(false),
+        PickApproach::Any => file = file.create(true),
+    }
+
+    file.open(name)
+        .map_err(|e| format!("failed to open database file: {e}"))?;
+    Ok(())
+}
+
+fn open_database(
+    name: &str,
+    shared: bool,
+    password: &Option<String>,
+) -> Result<(Database, Loaded), Box<dyn Error>> {
+    let mode = if shared {
+        LockMode::Shared
+    } else {
+        LockMode::Exclusive
+    };
+    Database::load_from_path(Path::new(name), password.clone(), mode)
+        .map_err(|e| format!("failed to read database: {e}").into())
+}
+
+///current version of a key that has not expired yet
+fn encryption_key<'d>(db: &'d Database, name: &KeyName) -> Result<&'d Entry, Box<dyn Error>> {
+    let Some(entry) = db.get_entry(&name.name) else {
+        return Err(match db.get(&name.name) {
+            Some(_) => format!("\"{name}\" is an older version, only the current one can ENCRYPT"),
+            None => format!("no key {name}"),
+        }
+        .into());
+    };
+    if let Some(expires) = entry.metadata.expires {
+        if entry.metadata.is_expired(Date::today()) {
+            return Err(format!(
+                "key \"{name}\" expired on {expires}, it can only be used to DECRYPT"
+            )
+            .into());
         }
     }
+    Ok(entry)
+}
+
+///key to decrypt with: the version recorded in the message or the one with
+///the expected fingerprint, the current one otherwise. Also returns the version
+///if an older one was picked
+fn pick_version<'d>(
+    db: &'d Database,
+    key_name: &KeyName,
+    version: Option<usize>,
+    expected: Option<Fingerprint>,
+) -> Result<(&'d StackedCypher, Option<usize>), Box<dyn Error>> {
+    let Some(entry) = db.get_entry(&key_name.name) else {
+        //either a version picked by hand or no such key
+        let key = db
+            .get(&key_name.name)
+            .ok_or_else(|| format!("no key {key_name}"))?;
+        return Ok((key, None));
+    };
+
+    let version = match version {
+        Some(version) => Some(version),
+        None => expected.and_then(|expected| {
+            entry
+                .versions()
+                .find(|(_, key)| Fingerprint::of(key) == expected)
+                .map(|(version, _)| version)
+        }),
+    }
+    .unwrap_or(entry.version);
+
+    let key = entry.revision(version).ok_or_else(|| {
+        format!("message was encrypted with version {version} of \"{key_name}\", which is no longer kept")
+    })?;
+    Ok((key, Some(version).filter(|&v| v != entry.version)))
+}
+
+///lines shown by DESCRIBE after the layers, only for what is known
+fn describe_metadata(metadata: &Metadata) -> String {
+    let mut lines = String::new();
+    if let Some(note) = &metadata.note {
+        lines += &format!("\nnote: {note}");
+    }
+    if !metadata.tags.is_empty() {
+        lines += &format!("\ntags: {}", metadata.tags.join(", "));
+    }
+    if let Some(owner) = &metadata.owner {
+        lines += &format!("\nowner: {owner}");
+    }
+    if let Some(created) = metadata.created {
+        lines += &format!("\ncreated: {}", metadata::format_timestamp(created));
+    }
+    if let Some(modified) = metadata.modified {
+        lines += &format!("\nmodified: {}", metadata::format_timestamp(modified));
+    }
+    if let Some(expires) = metadata.expires {
+        let expired = if metadata.is_expired(Date::today()) {
+            " (expired)"
+        } else {
+            ""
+        };
+        lines += &format!("\nexpires: {expires}{expired}");
+    }
+    lines
+}
+
+fn describe_changes(changes: &[Change]) -> String {
+    changes
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn recovered_message(database: &Database, error: &str) -> String {
+    format!(
+        " from backup {}: main file is damaged ({error}). SAVE to restore it",
+        database.backup_path().display()
+    )
+}
+
+fn build_layer(description: &AlgorithmDescription) -> Result<Layer, Box<dyn Error>> {
+    Ok(Layer::new(
+        description.padding,
+        description.style,
+        build_algorithm(description)?,
+    )
+    .with_pad_value(description.pad_value))
+}
+
+fn build_algorithm(description: &AlgorithmDescription) -> Result<Algorithm, Box<dyn Error>> {
+    Ok(match &description.algo_type {
+        AlgorithmType::Permutation(PermutationType::Generated(size)) => {
+            if description.reject_weak {
+                SimplePermutation::random_strong_with_size(*size)?.into()
+            } else {
+                SimplePermutation::random_with_size(*size)?.into()
+            }
+        }
+
+        AlgorithmType::Permutation(PermutationType::Manual(config)) => {
+            SimplePermutation::try_from(config.clone())
+                .ok_or_else(|| <Box<dyn Error>>::from("misconfigured permutation"))?
+                .into()
+        }
+
+        AlgorithmType::Permutation(PermutationType::Ranked(compact)) => {
+            SimplePermutation::from_compact_string(compact)?.into()
+        }
+
+        AlgorithmType::Permutation(PermutationType::Cycles(cycles, size)) => {
+            SimplePermutation::from_cycles(cycles, *size)?.into()
+        }
+
+        AlgorithmType::RailFence(None) if description.reject_weak => {
+            return Err("no rail fence is strong, its rows are found in a few attempts".into())
+        }
+        AlgorithmType::RailFence(None) => thread_rng().gen::<RailFenceCypher>().into(),
+
+        AlgorithmType::RailFence(Some((rows, columns))) => {
+            RailFenceCypher::try_new(*rows, *columns)?.into()
+        }
+        AlgorithmType::Vertical(None) if description.reject_weak => {
+            Strong::sample::<VerticalPermutation, _>(&mut thread_rng())?.into()
+        }
+        AlgorithmType::Vertical(None) => thread_rng().gen::<VerticalPermutation>().into(),
+        AlgorithmType::Vertical(Some((rows, columns, c))) => {
+            let permutation = SimplePermutation::try_from(c.clone()).ok_or_else(|| {
+                <Box<dyn Error>>::from("misconfigured permutation of vertical cypher")
+            })?;
+            VerticalPermutation::try_new(*rows, *columns, permutation)?.into()
+        }
+    })
 }
 
 fn escape(s: &str) -> String {
@@ -326,3 +1297,421 @@ fn unescape(s: &str) -> Result<String, Box<dyn Error>> {
 
     unescape_(s).map_err(|idx| format!("error while unescaping at {idx}").into())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::{interpreter::parse::command_parser, protection, ExecResult};
+
+    use super::Interpreter;
+
+    fn run(interpreter: &mut Interpreter, source: &str) -> String {
+        let program = command_parser::program(source).unwrap();
+        let mut messages = vec![];
+        for stmt in program {
+            match interpreter.visit_stmt(&stmt).unwrap() {
+                ExecResult::Message(message) => messages.push(message),
+                ExecResult::Exit => break,
+            }
+        }
+        messages.join("\n")
+    }
+
+    ///error of a single statement
+    fn fail(interpreter: &mut Interpreter, source: &str) -> String {
+        let program = command_parser::program(source).unwrap();
+        assert_eq!(program.len(), 1);
+        match interpreter.visit_stmt(&program[0]) {
+            Ok(_) => panic!("{source} did not fail"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    ///empty directory with `keys.db` and `team.db` in it
+    fn databases(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("permutations-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("team.db"), "").unwrap();
+        root
+    }
+
+    #[test]
+    fn attaches_databases() {
+        let root = databases("attach");
+        let (keys, team) = (root.join("keys.db"), root.join("team.db"));
+        let (keys, team) = (keys.display(), team.display());
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            &format!(
+                r#"CREATE DATABASE "{keys}";
+                ADD "A" AS PADDING CHAR PERMUTATION(1, 2, 0);
+                SAVE"#
+            ),
+        );
+
+        //the same alias or file cannot be open twice
+        assert!(fail(
+            &mut interpreter,
+            &format!(r#"ATTACH DATABASE "{keys}" AS other"#)
+        )
+        .contains("already open as main"));
+        assert!(fail(
+            &mut interpreter,
+            &format!(r#"ATTACH DATABASE "{team}" AS main"#)
+        )
+        .contains("already the alias"));
+        run(
+            &mut interpreter,
+            &format!(r#"ATTACH DATABASE "{team}" AS team"#),
+        );
+        assert!(fail(
+            &mut interpreter,
+            &format!(r#"ATTACH DATABASE "{team}" AS other"#)
+        )
+        .contains("already attached as team"));
+        assert!(fail(
+            &mut interpreter,
+            &format!(r#"ATTACH DATABASE "{keys}" AS team"#)
+        )
+        .contains("already the alias"));
+
+        //qualified names find keys of the attached database only
+        run(&mut interpreter, r#"COPY "A" TO team."B""#);
+        assert!(fail(&mut interpreter, r#"DESCRIBE "B""#).contains("no such entry"));
+        assert!(fail(&mut interpreter, r#"DESCRIBE team."A""#).contains("no such entry"));
+        assert!(fail(&mut interpreter, r#"DESCRIBE other."A""#).contains("no database"));
+        assert_eq!(
+            run(&mut interpreter, r#"FINGERPRINT team."B""#)
+                .split(": ")
+                .nth(1),
+            run(&mut interpreter, r#"FINGERPRINT main."A""#)
+                .split(": ")
+                .nth(1)
+        );
+        let encrypted = run(&mut interpreter, r#"ENCRYPT "secret" WITH team."B""#);
+        assert_eq!(
+            encrypted,
+            run(&mut interpreter, r#"ENCRYPT "secret" WITH "A""#)
+        );
+
+        //only the named database is saved
+        run(&mut interpreter, "SAVE team");
+        assert!(fs::read_to_string(root.join("team.db"))
+            .unwrap()
+            .contains("\"B\""));
+        run(&mut interpreter, r#"DELETE "A""#);
+        assert!(fs::read_to_string(root.join("keys.db"))
+            .unwrap()
+            .contains("\"A\""));
+
+        run(
+            &mut interpreter,
+            r#"ADD team."C" AS PADDING CHAR PERMUTATION(1, 0); DELETE team."B""#,
+        );
+        assert_eq!(
+            run(&mut interpreter, "DETACH team"),
+            "detached team, 2 unsaved changes were discarded"
+        );
+        assert!(fail(&mut interpreter, r#"DESCRIBE team."B""#).contains("no database"));
+        assert!(fail(&mut interpreter, "DETACH team").contains("no database"));
+
+        //the file can be attached again, without the discarded changes
+        run(
+            &mut interpreter,
+            &format!(r#"ATTACH DATABASE "{team}" AS team"#),
+        );
+        let list = run(&mut interpreter, "LIST team");
+        assert!(
+            list.starts_with("entries:\nB ") && list.ends_with("(1 total)"),
+            "{list}"
+        );
+        assert_eq!(run(&mut interpreter, "DETACH team"), "detached team");
+
+        drop(interpreter);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn concatenates_keys() {
+        use crate::interpreter::parse::{KeyDefinition, Stmt};
+
+        let names = |source: &str| match command_parser::program(source).as_deref() {
+            Ok(
+                [Stmt::Add {
+                    definition: KeyDefinition::Concat(names),
+                    ..
+                }],
+            ) => names.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            _ => panic!("{source} is not a concatenation"),
+        };
+        assert_eq!(names(r#"ADD "C" AS "A" + "B""#), ["A", "B"]);
+        assert_eq!(
+            names(r#"ADD "C" AS "A"+team."B" + "A""#),
+            ["A", "team.B", "A"]
+        );
+        //one kind of combination per key, THEN merges layers and + does not
+        assert!(command_parser::program(r#"ADD "C" AS "A" + "B" THEN "A""#).is_err());
+        assert!(command_parser::program(r#"ADD "C" AS "A" THEN "B" + "A""#).is_err());
+        assert!(command_parser::program(r#"ADD "C" AS "A" +"#).is_err());
+
+        let root = databases("concat");
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            &format!(
+                r#"CREATE DATABASE "{}/keys.db";
+                ADD "A" AS PADDING CHAR PERMUTATION(1, 2, 0);
+                ADD "B" AS PADDING CHAR PERMUTATION(1, 0, 2);
+                ADD "C" AS "A" + "B";
+                ADD "D" AS "A" THEN "B""#,
+                root.display()
+            ),
+        );
+        let db = interpreter.db.as_ref().unwrap();
+        let (a, b) = (db.get("A").unwrap(), db.get("B").unwrap());
+        assert_eq!(db.get("C"), Some(&a.concat(b)));
+        assert_eq!(db.get("C").unwrap().items().count(), 2);
+        assert_eq!(db.get("D").unwrap().items().count(), 1);
+        let data = "frogs are green".as_bytes();
+        let expected = b.encrypt(&a.encrypt(data).unwrap().1).unwrap().1;
+        assert_ne!(expected, a.encrypt(&b.encrypt(data).unwrap().1).unwrap().1);
+        for key in ["C", "D"] {
+            assert_eq!(db.get(key).unwrap().encrypt(data).unwrap().1, expected);
+        }
+
+        //copies and renames are changes to save like any other
+        run(&mut interpreter, "SAVE");
+        run(&mut interpreter, r#"COPY "A" TO "E"; RENAME "B" TO "F""#);
+        let changes = interpreter.db.as_ref().unwrap().unsaved_changes();
+        let changes = changes.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(changes, [r#"deleted "B""#, r#"added "E""#, r#"added "F""#]);
+        assert!(run(&mut interpreter, "STATUS").contains("(3 total)"));
+        run(&mut interpreter, "UNDO; UNDO");
+        assert!(interpreter
+            .db
+            .as_ref()
+            .unwrap()
+            .unsaved_changes()
+            .is_empty());
+
+        drop(interpreter);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn edits_layers() {
+        const CYCLE: &str = "PADDING BYTE PERMUTATION(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 0)";
+        const WEAK: &str = r#"(warning: weak key, see STRENGTH "K")"#;
+        let root = databases("layers");
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            &format!(
+                r#"CREATE DATABASE "{}/keys.db";
+                ADD "K" AS [{CYCLE}, PADDING CHAR PERMUTATION(2, 0, 1)]"#,
+                root.display()
+            ),
+        );
+        let original = interpreter.db.as_ref().unwrap().get("K").unwrap().clone();
+
+        assert_eq!(
+            run(&mut interpreter, r#"SWAP LAYERS 0 1 OF "K""#),
+            r#"swapped layers 0 and 1 of "K", now version 2"#
+        );
+        //edits that leave the key as it is do not make new versions
+        assert_eq!(
+            fail(&mut interpreter, r#"SWAP LAYERS 1 1 OF "K""#),
+            "layer 1 cannot be swapped with itself"
+        );
+        assert_eq!(
+            fail(
+                &mut interpreter,
+                &format!(r#"REPLACE LAYER 1 OF "K" WITH {CYCLE}"#)
+            ),
+            r#"the edit does not change "K""#
+        );
+        assert_eq!(
+            fail(&mut interpreter, r#"REMOVE LAYER 2 FROM "K""#),
+            "no layer 2, the key has 2 layer(s) numbered from 0"
+        );
+
+        assert_eq!(
+            run(
+                &mut interpreter,
+                r#"REPLACE LAYER 1 OF "K" WITH PADDING BYTE PERMUTATION(0, 1, 2, 3)"#
+            ),
+            format!(r#"replaced layer 1 of "K", now version 3 {WEAK}"#)
+        );
+        assert_eq!(
+            run(&mut interpreter, r#"REMOVE LAYER 1 FROM "K""#),
+            format!(r#"removed layer 1 from "K", now version 4 {WEAK}"#)
+        );
+        assert_eq!(
+            run(
+                &mut interpreter,
+                &format!(r#"INSERT LAYER 1 INTO "K" AS {CYCLE}"#)
+            ),
+            r#"inserted layer 1 into "K", now version 5"#
+        );
+
+        let db = interpreter.db.as_ref().unwrap();
+        let entry = db.get_entry("K").unwrap();
+        assert_eq!(entry.version, 5);
+        assert_eq!(
+            entry.history.iter().map(|r| r.version).collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+        assert_eq!(db.get("K@1"), Some(&original));
+        //the same layers as after the swap, but a version of its own
+        assert_eq!(db.get("K@2"), Some(&entry.cypher));
+        assert_ne!(entry.cypher, original);
+
+        let description = run(&mut interpreter, r#"DESCRIBE "K""#);
+        assert!(
+            description.contains("layers numbered from 0")
+                && description.contains("layer 0: ")
+                && description.contains("layer 1: ")
+                && !description.contains("layer 2: "),
+            "{description}"
+        );
+
+        drop(interpreter);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn journals_of_attached_databases() {
+        let root = databases("journals");
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            &format!(
+                r#"CREATE DATABASE "{0}/keys.db";
+                ATTACH DATABASE "{0}/team.db" AS team;
+                ADD "A" AS PADDING CHAR PERMUTATION(1, 2, 0);
+                COPY "A" TO team."B""#,
+                root.display()
+            ),
+        );
+
+        assert!(run(&mut interpreter, "UNDO team").contains("\"B\""));
+        assert!(fail(&mut interpreter, "UNDO team").contains("nothing"));
+        assert_eq!(
+            run(&mut interpreter, "STATUS team"),
+            "no unsaved changes\n0 steps to undo, 1 to redo"
+        );
+        assert!(run(&mut interpreter, "STATUS").contains("1 steps to undo"));
+
+        run(
+            &mut interpreter,
+            r#"REDO team; BEGIN team; DELETE team."B"; ADD team."C" AS PADDING CHAR PERMUTATION(1, 0)"#,
+        );
+        assert!(fail(&mut interpreter, "SAVE team").contains("transaction"));
+        run(&mut interpreter, "SAVE");
+        run(&mut interpreter, "ROLLBACK team");
+        let list = run(&mut interpreter, "LIST team");
+        assert!(
+            list.starts_with("entries:\nB ") && list.ends_with("(1 total)"),
+            "{list}"
+        );
+
+        run(
+            &mut interpreter,
+            r#"CHANGE PASSWORD team "secret"; SAVE team"#,
+        );
+        assert!(protection::is_sealed(
+            &fs::read(root.join("team.db")).unwrap()
+        ));
+        assert!(!protection::is_sealed(
+            &fs::read(root.join("keys.db")).unwrap()
+        ));
+        assert!(run(&mut interpreter, "UNDO").contains("\"A\""));
+
+        drop(interpreter);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn rotates_directory() {
+        let root = std::env::temp_dir().join(format!("permutations-{}-rotate", std::process::id()));
+        let files = root.join("files");
+        fs::create_dir_all(&files).unwrap();
+        let (root, dir) = (root.display(), files.display());
+
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            &format!(
+                r#"CREATE DATABASE "{root}/keys.db";
+                ADD "A" AS PADDING CHAR PERMUTATION(1, 2, 0);
+                ENCRYPT "old version" WITH "A" INTO "{dir}/old.bin";
+                ADD "A" AS PADDING CHAR PERMUTATION(2, 0, 1);
+                ENCRYPT "current version" WITH "A" INTO "{dir}/current.bin";
+                ADD "B" AS PADDING BYTE PERMUTATION(3, 1, 0, 2);
+                ENCRYPT "already rotated" WITH "B" INTO "{dir}/rotated.bin";
+                ADD "C" AS PADDING BYTE PERMUTATION(1, 0);
+                ENCRYPT "foreign key" WITH "C" INTO "{dir}/foreign.bin""#
+            ),
+        );
+        fs::write(files.join("current.bin.tmp"), b"interrupted").unwrap();
+        let read = |name: &str| fs::read(files.join(name)).unwrap();
+        let before = ["old.bin", "current.bin", "rotated.bin", "foreign.bin"].map(read);
+
+        let report = run(
+            &mut interpreter,
+            &format!(r#"ROTATE "A" TO "B" IN "{dir}" DRY RUN"#),
+        );
+        assert!(
+            report.ends_with("2 rotated, 1 skipped, 1 failed (dry run, no files were changed)"),
+            "{report}"
+        );
+        assert!(!report.contains(".tmp"), "{report}");
+        assert_eq!(
+            ["old.bin", "current.bin", "rotated.bin", "foreign.bin"].map(read),
+            before
+        );
+
+        let report = run(
+            &mut interpreter,
+            &format!(r#"ROTATE "A" TO "B" IN "{dir}""#),
+        );
+        assert!(
+            report.ends_with("2 rotated, 1 skipped, 1 failed"),
+            "{report}"
+        );
+        assert!(
+            report.contains("failed") && report.contains("foreign.bin"),
+            "{report}"
+        );
+        assert_eq!(read("foreign.bin"), before[3]);
+        assert_eq!(read("rotated.bin"), before[2]);
+
+        for (name, expected) in [
+            ("old.bin", "old version"),
+            ("current.bin", "current version"),
+            ("rotated.bin", "already rotated"),
+        ] {
+            run(
+                &mut interpreter,
+                &format!(r#"DECRYPT FROM "{dir}/{name}" WITH "B" INTO "{root}/out.txt""#),
+            );
+            let decrypted = fs::read_to_string(root.to_string() + "/out.txt").unwrap();
+            assert_eq!(decrypted, expected);
+        }
+
+        let report = run(
+            &mut interpreter,
+            &format!(r#"ROTATE "A" TO "B" IN "{dir}""#),
+        );
+        assert!(
+            report.ends_with("0 rotated, 3 skipped, 1 failed"),
+            "{report}"
+        );
+
+        drop(interpreter);
+        fs::remove_dir_all(root.to_string()).unwrap();
+    }
+}
@@ -1,3 +1,7 @@
+use std::fmt::Display;
+
+use crate::metadata::Metadata;
+
 pub enum PickApproach {
     Create,
     Load,
@@ -8,27 +12,139 @@ pub enum Stmt {
     DatabasePick {
         name: String,
         create: PickApproach,
+        shared: bool,
+        password: Option<String>,
+        ///`main` if not given
+        alias: Option<String>,
+    },
+    ///opens one more database next to the main one
+    Attach {
+        name: String,
+        alias: String,
+        shared: bool,
+        password: Option<String>,
+    },
+    Detach(String),
+    ChangePassword {
+        database: Option<String>,
+        ///`None` removes the password
+        password: Option<String>,
+    },
+    ///`database` is an alias, the main database is used without it
+    Save {
+        database: Option<String>,
+        compact: bool,
+        ///keep the previous version of the file as `.bak`
+        backup: bool,
+        ///combine with changes made on disk by others
+        merge: bool,
+    },
+    List {
+        database: Option<String>,
+        ///keys having the tag, all when `None`
+        tag: Option<String>,
     },
-    Save,
-    List,
-    Reload,
+    Reload(Option<String>),
+    Undo(Option<String>),
+    Redo(Option<String>),
+    Begin(Option<String>),
+    Commit(Option<String>),
+    Rollback(Option<String>),
+    Status(Option<String>),
     Exit,
-    Describe(String),
-    Delete(String),
+    Describe(KeyName),
+    Strength(KeyName),
+    Delete(KeyName),
+    History(KeyName),
+    ///current version of the key with its note, tags and expiry
+    Copy {
+        from: KeyName,
+        to: KeyName,
+    },
+    ///within one database, with all versions
+    Rename {
+        from: KeyName,
+        to: KeyName,
+    },
+    ///one key or all of them, `None` exports all
+    Export {
+        name: Option<KeyName>,
+        to: String,
+    },
+    ///`name` renames the only key of the file
+    Import {
+        from: String,
+        name: Option<KeyName>,
+    },
+    ///re-encrypt files of the directory encrypted with `from` using `to`
+    Rotate {
+        from: KeyName,
+        to: KeyName,
+        directory: String,
+        ///only check that every file can be rotated
+        dry_run: bool,
+    },
+    ///share files of the key, any `threshold` of them rebuild it
+    Split {
+        name: KeyName,
+        count: usize,
+        threshold: usize,
+        ///current directory if not given
+        directory: Option<String>,
+    },
+    Combine {
+        from: Vec<String>,
+        name: KeyName,
+    },
+    ///the edited key becomes a new version
+    EditLayers {
+        name: KeyName,
+        edit: LayerEdit,
+    },
+    ///keep that many newest versions
+    Prune {
+        name: KeyName,
+        keep: usize,
+    },
     Encrypt {
         from: DataSource,
-        key: String,
+        key: KeyName,
         to: DataTarget,
     },
     Decrypt {
         from: DecryptSource,
-        key: String,
+        key: KeyName,
+        ///expected key, files carry their own
+        fingerprint: Option<String>,
         to: DataTarget,
     },
+    Fingerprint(KeyName),
     Add {
-        name: String,
-        algos: Vec<AlgorithmDescription>,
+        name: KeyName,
+        definition: KeyDefinition,
+        ///note, tags and expiry, the rest is filled in when adding
+        metadata: Metadata,
     },
+    GuessBlockSize {
+        from: DataSource,
+        max_size: Option<usize>,
+    },
+}
+
+///name of a key, `team."Alice"` refers to the database attached as `team`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyName {
+    pub database: Option<String>,
+    pub name: String,
+}
+
+impl Display for KeyName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.database {
+            Some(database) => write!(f, "{database}.{}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
 }
 
 pub enum DataSource {
@@ -42,15 +158,35 @@ pub enum DataTarget {
 }
 
 pub enum DecryptSource {
-    ConsoleString(Vec<usize>, String),
+    ///sizes may be omitted for keys with self-delimiting padding
+    ConsoleString(Option<Vec<usize>>, String),
     ConsoleRaw(Vec<usize>, Vec<u8>),
     File(String),
 }
 
+///layers are numbered from 0, as in DESCRIBE
+pub enum LayerEdit {
+    Insert(usize, AlgorithmDescription),
+    Remove(usize),
+    Replace(usize, AlgorithmDescription),
+    Swap(usize, usize),
+}
+
+pub enum KeyDefinition {
+    Algorithms(Vec<AlgorithmDescription>),
+    InverseOf(KeyName),
+    Then(Vec<KeyName>),
+    ///layers of all keys, without merging
+    Concat(Vec<KeyName>),
+}
+
 pub struct AlgorithmDescription {
     pub padding: crate::algorithms::stacked::PadApproach,
+    pub pad_value: crate::algorithms::stacked::PadValue,
     pub style: crate::algorithms::stacked::EncryptionStyle,
     pub algo_type: AlgorithmType,
+    ///regenerate random keys until they are not weak
+    pub reject_weak: bool,
 }
 
 pub enum AlgorithmType {
@@ -62,13 +198,17 @@ pub enum AlgorithmType {
 pub enum PermutationType {
     Generated(usize),
     Manual(Vec<usize>),
+    Cycles(Vec<Vec<usize>>, Option<usize>),
+    Ranked(String),
 }
 
 peg::parser! {
 
     pub grammar command_parser() for str {
 
-use crate::algorithms::stacked::{EncryptionStyle, PadApproach};
+use crate::algorithms::stacked::{EncryptionStyle, GroupRemainder, PadApproach, PadValue};
+use crate::datastructs::MAX_BIT_FIELD_WIDTH;
+use crate::metadata::Date;
 
 
         rule string() -> String =
@@ -90,43 +230,159 @@ use crate::algorithms::stacked::{EncryptionStyle, PadApproach};
 
         pub rule stmt() -> Stmt =
             database()/
+            attach() /
             save()/
+            journal() /
+            change_password() /
             list() /
             reload() /
             describe() /
+            strength() /
+            fingerprint() /
             encrypt() /
             decrypt() /
             delete() /
+            copy() /
+            history() /
+            rotate() /
+            sharing() /
+            layers() /
+            exchange() /
             add() /
+            guess_block_size() /
             exit()
 
         rule database() -> Stmt =
-            _ pick: pick_approach()? _ "DATABASE" __ s:string() _  {Stmt::DatabasePick{
+            _ pick: pick_approach()? _ "DATABASE" __ s:string() shared:(__ "SHARED")?
+            password:(__ "PASSWORD" __ p:string() {p})? alias:(__ "AS" __ a:alias() {a})? _  {Stmt::DatabasePick{
                 name:s,
-                create: pick.unwrap_or(PickApproach::Any)
+                create: pick.unwrap_or(PickApproach::Any),
+                shared: shared.is_some(),
+                password,
+                alias,
             }}
 
+        rule attach() -> Stmt =
+            _ "ATTACH" __ "DATABASE" __ name:string() __ "AS" __ alias:alias() shared:(__ "SHARED")?
+            password:(__ "PASSWORD" __ p:string() {p})? _ {
+                Stmt::Attach{ name, alias, shared: shared.is_some(), password }
+            }/
+            _ "DETACH" __ alias:alias() _ {
+                Stmt::Detach(alias)
+            }
+
+        ///words that may follow an optional alias
+        rule reserved() =
+            ("COMPACT" / "NO" / "MERGE" / "WHERE" / "NONE") !['a'..='z' | 'A'..='Z' | '0'..='9' | '_']
+
+        rule alias() -> String =
+            !reserved() s:$(['a'..='z' | 'A'..='Z' | '_'] ['a'..='z' | 'A'..='Z' | '0'..='9' | '_']*) {
+                s.to_string()
+            }
+
+        rule key() -> KeyName =
+            database:(a:alias() "." {a})? name:string() {
+                KeyName{ database, name }
+            }
+
+        rule change_password() -> Stmt =
+            _ "CHANGE" __ "PASSWORD" database:(__ a:alias() {a})? __ password:(p:string() {Some(p)} / "NONE" {None}) _ {
+                Stmt::ChangePassword{ database, password }
+            }
+
         rule save() -> Stmt =
-            _ "SAVE" _ {Stmt::Save}
+            _ "SAVE" database:(__ a:alias() {a})? compact:(__ "COMPACT")? no_backup:(__ "NO" __ "BACKUP")? merge:(__ "MERGE")? _ {
+                Stmt::Save{
+                    database,
+                    compact: compact.is_some(),
+                    backup: no_backup.is_none(),
+                    merge: merge.is_some(),
+                }
+            }
 
         rule reload() -> Stmt =
-            _ "RELOAD" _ {Stmt::Reload}
+            _ "RELOAD" database:(__ a:alias() {a})? _ {Stmt::Reload(database)}
 
         rule list() -> Stmt =
-            _ "LIST" _ {Stmt::List}
+            _ "LIST" database:(__ a:alias() {a})? tag:(__ "WHERE" __ "TAG" __ t:string() {t})? _ {
+                Stmt::List{ database, tag }
+            }
 
         rule describe() -> Stmt =
-            _ "DESCRIBE" __ n:string() _ {
+            _ "DESCRIBE" __ n:key() _ {
                 Stmt::Describe(n)
             }
 
+        rule fingerprint() -> Stmt =
+            _ "FINGERPRINT" __ n:key() _ {
+                Stmt::Fingerprint(n)
+            }
+
+        rule strength() -> Stmt =
+            _ "STRENGTH" __ n:key() _ {
+                Stmt::Strength(n)
+            }
+
         rule delete() -> Stmt =
-            _ "DELETE" __ n:string() _ {
+            _ "DELETE" __ n:key() _ {
                 Stmt::Delete(n)
             }
 
+        rule copy() -> Stmt =
+            _ "COPY" __ from:key() __ "TO" __ to:key() _ {
+                Stmt::Copy{ from, to }
+            }/
+            _ "RENAME" __ from:key() __ "TO" __ to:key() _ {
+                Stmt::Rename{ from, to }
+            }
+
+        rule history() -> Stmt =
+            _ "HISTORY" __ n:key() _ {
+                Stmt::History(n)
+            }/
+            _ "PRUNE" __ n:key() __ "KEEP" __ keep:number() _ {
+                Stmt::Prune{ name: n, keep }
+            }
+
+        rule exchange() -> Stmt =
+            _ "EXPORT" __ name:("ALL" {None} / n:key() {Some(n)}) __ "INTO" __ to:string() _ {
+                Stmt::Export{ name, to }
+            }/
+            _ "IMPORT" __ "FROM" __ from:string() name:(__ "AS" __ n:key() {n})? _ {
+                Stmt::Import{ from, name }
+            }
+
+        rule sharing() -> Stmt =
+            _ "SPLIT" __ name:key() __ "INTO" __ count:number() __ "SHARES" __ "THRESHOLD" __ threshold:number()
+            directory:(__ "IN" __ d:string() {d})? _ {
+                Stmt::Split{ name, count, threshold, directory }
+            }/
+            _ "COMBINE" __ "FROM" _ "[" _ from:string()**(_ "," _) _ "]" __ "AS" __ name:key() _ {
+                Stmt::Combine{ from, name }
+            }
+
+        rule layers() -> Stmt =
+            _ "INSERT" __ "LAYER" __ i:number() __ "INTO" __ name:key() __ "AS" __ a:algorithm() _ {
+                Stmt::EditLayers{ name, edit: LayerEdit::Insert(i, a) }
+            }/
+            _ "REMOVE" __ "LAYER" __ i:number() __ "FROM" __ name:key() _ {
+                Stmt::EditLayers{ name, edit: LayerEdit::Remove(i) }
+            }/
+            _ "REPLACE" __ "LAYER" __ i:number() __ "OF" __ name:key() __ "WITH" __ a:algorithm() _ {
+                Stmt::EditLayers{ name, edit: LayerEdit::Replace(i, a) }
+            }/
+            _ "SWAP" __ "LAYERS" __ i:number() __ j:number() __ "OF" __ name:key() _ {
+                Stmt::EditLayers{ name, edit: LayerEdit::Swap(i, j) }
+            }
+
+        rule rotate() -> Stmt =
+            _ "ROTATE" __ from:key() __ "TO" __ to:key() __ "IN" __ directory:string()
+            dry_run:(__ "DRY" __ "RUN")? _ {
+                Stmt::Rotate{ from, to, directory, dry_run: dry_run.is_some() }
+            }
+
         rule encrypt() -> Stmt =
-            _ "ENCRYPT" __ source:encrypt_source() __ "WITH" __ key:string() _ target:encrypt_target()? _ {
+            _ "ENCRYPT" __ source:encrypt_source() __ "WITH" __ key:key() _ target:encrypt_target()? _ {
                 let target = target.unwrap_or(DataTarget::Console);
 
                 Stmt::Encrypt{
@@ -134,17 +390,53 @@ use crate::algorithms::stacked::{EncryptionStyle, PadApproach};
             }
 
         rule decrypt() -> Stmt =
-        _ "DECRYPT" __ source:decrypt_source() __ "WITH" __ key:string() _ target:encrypt_target()? _ {
+        _ "DECRYPT" __ source:decrypt_source() fingerprint:(__ "FINGERPRINT" __ f:string() {f})? __ "WITH" __ key:key() _ target:encrypt_target()? _ {
             let target = target.unwrap_or(DataTarget::Console);
 
             Stmt::Decrypt{
-                    from: source, key, to: target }
+                    from: source, key, fingerprint, to: target }
         }
 
 
         rule add() -> Stmt =
-            _ "ADD" __ n:string() __ "AS" __ a:one_or_more_algorithms() _ {
-                Stmt::Add{ name: n, algos: a }
+            _ "ADD" __ n:key() __ "AS" __ d:key_definition()
+            note:(__ "NOTE" __ s:string() {s})?
+            tags:(__ "TAGS" _ "[" _ t:string()**(_ "," _) _ "]" {t})?
+            expires:(__ "EXPIRES" __ d:date() {d})? _ {
+                Stmt::Add{
+                    name: n,
+                    definition: d,
+                    metadata: Metadata {
+                        note,
+                        tags: tags.unwrap_or_default(),
+                        expires,
+                        ..Default::default()
+                    },
+                }
+            }
+
+        rule date() -> Date =
+            d:$(['0'..='9']*<4> "-" ['0'..='9']*<2> "-" ['0'..='9']*<2>) {?
+                d.parse().or(Err("valid date"))
+            }
+
+        rule key_definition() -> KeyDefinition =
+            "INVERSE" __ "OF" __ n:key() {
+                KeyDefinition::InverseOf(n)
+            }/
+            n:key()**<2,>(__ "THEN" __) {
+                KeyDefinition::Then(n)
+            }/
+            n:key()**<2,>(_ "+" _) {
+                KeyDefinition::Concat(n)
+            }/
+            a:one_or_more_algorithms() {
+                KeyDefinition::Algorithms(a)
+            }
+
+        rule guess_block_size() -> Stmt =
+            _ "GUESS" __ "BLOCKSIZE" __ source:encrypt_source() max_size:(__ "UP" __ "TO" __ n:number() {n})? _ {
+                Stmt::GuessBlockSize{ from: source, max_size }
             }
 
         rule one_or_more_algorithms() -> Vec<AlgorithmDescription> =
@@ -153,6 +445,14 @@ use crate::algorithms::stacked::{EncryptionStyle, PadApproach};
             }/
             a:algorithm() {vec![a]}
 
+        rule journal() -> Stmt =
+            _ "UNDO" database:(__ a:alias() {a})? _ {Stmt::Undo(database)} /
+            _ "REDO" database:(__ a:alias() {a})? _ {Stmt::Redo(database)} /
+            _ "BEGIN" database:(__ a:alias() {a})? _ {Stmt::Begin(database)} /
+            _ "COMMIT" database:(__ a:alias() {a})? _ {Stmt::Commit(database)} /
+            _ "ROLLBACK" database:(__ a:alias() {a})? _ {Stmt::Rollback(database)} /
+            _ "STATUS" database:(__ a:alias() {a})? _ {Stmt::Status(database)}
+
         rule exit() -> Stmt =
             _ "EXIT" _ {
                 Stmt::Exit
@@ -161,29 +461,47 @@ use crate::algorithms::stacked::{EncryptionStyle, PadApproach};
         rule algorithm() -> AlgorithmDescription =
             pad: pad_style() __ style: encrypt_style() __ desc: algorithm_style() {
                 AlgorithmDescription{
-                    padding:pad,
+                    padding:pad.0,
+                    pad_value:pad.1,
                      style,
-                    algo_type:desc
+                    algo_type:desc.0,
+                    reject_weak:desc.1
                 }
             }
 
 
-        rule algorithm_style() -> AlgorithmType =
-            "PERMUTATION" _ "(" _ "GENERATED" _ "(" _ size:number() _ ")" _ ")" {
-                AlgorithmType::Permutation(PermutationType::Generated(size))
+        rule strong() -> bool =
+            s:(__ "STRONG")? {
+                s.is_some()
+            }
+
+        rule algorithm_style() -> (AlgorithmType, bool) =
+            "PERMUTATION" _ "(" _ "GENERATED" _ "(" _ size:number() _ ")" strong:strong() _ ")" {
+                (AlgorithmType::Permutation(PermutationType::Generated(size)), strong)
+            }/
+            "RAILFENCE" _ "(" _ "GENERATED" strong:strong() _ ")" {
+                 (AlgorithmType::RailFence(None), strong)
+            }/
+            "VERTICAL" _ "(" _ "GENERATED" strong:strong() _ ")" {
+                 (AlgorithmType::Vertical(None), strong)
+            }/
+            a:manual_algorithm_style() {
+                (a, false)
+            }
+
+        rule manual_algorithm_style() -> AlgorithmType =
+            "PERMUTATION" _ "(" _ "RANK" __ r:string() _ ")" {
+                AlgorithmType::Permutation(PermutationType::Ranked(r))
+            }/
+            "PERMUTATION" _ "(" _ "CYCLES" _ c:cycle()+ _ size:("SIZE" __ n:number() {n})? _ ")" {
+                AlgorithmType::Permutation(PermutationType::Cycles(c, size))
             }/
              "PERMUTATION" _ "(" _ n:number()++("," _) ","? _ ")" {
                  AlgorithmType::Permutation(PermutationType::Manual(n))
             }/
-            "RAILFENCE" _ "(" _ "GENERATED" _ ")" {
-                 AlgorithmType::RailFence(None)
-            }/
             "RAILFENCE" _ "(" _ a:number() _ "," _ b:number() _ ")" {
                  AlgorithmType::RailFence(Some((a, b)))
             }/
-            "VERTICAL" _ "(" _ "GENERATED" _ ")" {
-                 AlgorithmType::Vertical(None)
-            }/
             "VERTICAL" _ "(" _ a:number() _ "," _ b:number() _ "," _ "[" _ numbers: number()++(_ "," _) _ ","? _ "]" _ ")" {
                 AlgorithmType::Vertical(Some((a, b, numbers)))
             }
@@ -191,11 +509,43 @@ use crate::algorithms::stacked::{EncryptionStyle, PadApproach};
 
 
 
-        rule pad_style() -> PadApproach =
-            "PADDING" {PadApproach::Padding}/
-            "UNPADDING" {PadApproach::Unpadding}
+        rule cycle() -> Vec<usize> =
+            "(" _ n:number()++__ _ ")" _ {
+                n
+            }
+
+        rule pad_style() -> (PadApproach, PadValue) =
+            "PADDING" __ "DELIMITED" {
+                (PadApproach::Padding, PadValue::Delimited)
+            }/
+            "PADDING" v:(__ "PAD" __ v:pad_value() {v})? {
+                (PadApproach::Padding, v.unwrap_or_default())
+            }/
+            "UNPADDING" {(PadApproach::Unpadding, PadValue::Default)} /
+            "STEALING" {(PadApproach::Stealing, PadValue::Default)}
+
+        rule pad_value() -> PadValue =
+            c:character() {
+                PadValue::Fixed(c as u32)
+            }/
+            n:number() {?
+                u32::try_from(n).map(PadValue::Fixed).or(Err("pad value fitting into 32 bits"))
+            }/
+            "RANDOM" {
+                PadValue::Random
+            }/
+            "FREQUENCY" {
+                PadValue::Frequency
+            }
 
         rule encrypt_style() -> EncryptionStyle =
+            "BITS" _ "(" n:number() _ ")" {?
+                if (1..=MAX_BIT_FIELD_WIDTH).contains(&n) {
+                    Ok(EncryptionStyle::Bits(n))
+                } else {
+                    Err("bit field width from 1 to 32")
+                }
+            }/
             "BIT" {
                 EncryptionStyle::Bit
             }/
@@ -205,10 +555,39 @@ use crate::algorithms::stacked::{EncryptionStyle, PadApproach};
             "CHAR" {
                 EncryptionStyle::Char
             }/
-            "GROUP" _ "(" n:number() _ ")" {
-                EncryptionStyle::Group(n)
+            "GROUP" _ "(" n:number() _ r:("," _ r:group_remainder() _ {r})? ")" {?
+                if n > 0 {
+                    Ok(EncryptionStyle::Group(n, r.unwrap_or(GroupRemainder::Reject)))
+                } else {
+                    Err("group size of at least 1")
+                }
+            }/
+            "WORD" {
+                EncryptionStyle::Word
+            }/
+            "LINE" {
+                EncryptionStyle::Line
+            }/
+            "GRAPHEME" {
+                EncryptionStyle::Grapheme
+            }
+
+
+        rule group_remainder() -> GroupRemainder =
+            "PAD" __ c:character() {
+                GroupRemainder::Pad(c)
+            }/
+            "BYPASS" {
+                GroupRemainder::Bypass
+            }/
+            "RECORD" {
+                GroupRemainder::Record
             }
 
+        rule character() -> char =
+            "'" c:[^'\''] "'" {
+                c
+            }
 
         rule encrypt_source() -> DataSource =
             "FROM" __ s:string() {
@@ -229,7 +608,11 @@ use crate::algorithms::stacked::{EncryptionStyle, PadApproach};
             } /
 
             d:string_data() {
-                DecryptSource::ConsoleString(d.0, d.1)
+                DecryptSource::ConsoleString(Some(d.0), d.1)
+            } /
+
+            s:string() {
+                DecryptSource::ConsoleString(None, s)
             } /
 
             d:binary_data() {
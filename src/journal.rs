@@ -0,0 +1,116 @@
+use std::{error::Error, fmt::Display};
+
+use crate::database::Entry;
+
+///one key before and after an edit, `None` if it did not exist
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub name: String,
+    pub before: Option<Entry>,
+    pub after: Option<Entry>,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let action = match (&self.before, &self.after) {
+            (None, _) => "added",
+            (_, None) => "deleted",
+            _ => "replaced",
+        };
+        write!(f, "{action} \"{}\"", self.name)
+    }
+}
+
+///edits that can be undone and redone. Edits made inside a transaction are
+///undone together
+#[derive(Default)]
+pub struct Journal {
+    done: Vec<Vec<Change>>,
+    undone: Vec<Vec<Change>>,
+    transaction: Option<Vec<Change>>,
+}
+
+const IN_TRANSACTION: &str = "a transaction is open, COMMIT or ROLLBACK it first";
+
+impl Journal {
+    pub fn record(&mut self, change: Change) {
+        match &mut self.transaction {
+            Some(changes) => changes.push(change),
+            None => self.done.push(vec![change]),
+        }
+        self.undone.clear();
+    }
+
+    ///several changes undone as one step
+    pub fn record_step(&mut self, changes: Vec<Change>) {
+        match &mut self.transaction {
+            Some(transaction) => transaction.extend(changes),
+            None => self.done.push(changes),
+        }
+        self.undone.clear();
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    pub fn begin(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.in_transaction() {
+            return Err("a transaction is already open".into());
+        }
+        self.transaction = Some(vec![]);
+        Ok(())
+    }
+
+    ///returns number of changes in the transaction
+    pub fn commit(&mut self) -> Result<usize, Box<dyn Error>> {
+        let changes = self.transaction.take().ok_or("no transaction is open")?;
+        let count = changes.len();
+        if !changes.is_empty() {
+            self.done.push(changes);
+        }
+        Ok(count)
+    }
+
+    ///changes made in the transaction, they are to be reverted
+    pub fn rollback(&mut self) -> Result<Vec<Change>, Box<dyn Error>> {
+        Ok(self.transaction.take().ok_or("no transaction is open")?)
+    }
+
+    ///changes of the last step, they are to be reverted
+    pub fn undo(&mut self) -> Result<Vec<Change>, Box<dyn Error>> {
+        if self.in_transaction() {
+            return Err(IN_TRANSACTION.into());
+        }
+        let step = self.done.pop().ok_or("nothing to undo")?;
+        self.undone.push(step.clone());
+        Ok(step)
+    }
+
+    ///changes of the last undone step, they are to be applied again
+    pub fn redo(&mut self) -> Result<Vec<Change>, Box<dyn Error>> {
+        if self.in_transaction() {
+            return Err(IN_TRANSACTION.into());
+        }
+        let step = self.undone.pop().ok_or("nothing to redo")?;
+        self.done.push(step.clone());
+        Ok(step)
+    }
+
+    pub fn undo_steps(&self) -> usize {
+        self.done.len()
+    }
+
+    pub fn redo_steps(&self) -> usize {
+        self.undone.len()
+    }
+
+    ///changes made in the open transaction
+    pub fn pending(&self) -> usize {
+        self.transaction.as_ref().map_or(0, Vec::len)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Journal::default();
+    }
+}
@@ -9,7 +9,14 @@ use interpreter::{interpreter::Interpreter, parse};
 mod algorithms;
 mod database;
 mod datastructs;
+mod envelope;
+mod exchange;
+mod grapheme;
 mod interpreter;
+mod journal;
+mod metadata;
+mod protection;
+mod sharing;
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,152 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SECONDS_IN_DAY: u64 = 24 * 60 * 60;
+
+///calendar date in the proleptic Gregorian calendar
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    ///date `days` after 1970-01-01, from Howard Hinnant's `civil_from_days`
+    pub fn from_days(days: i64) -> Self {
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        Date { year, month, day }
+    }
+
+    ///inverse of `from_days`
+    pub fn days(&self) -> i64 {
+        let year = self.year - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let month = self.month as i64;
+        let doy =
+            (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146097 + doe - 719468
+    }
+
+    pub fn today() -> Self {
+        Date::from_days((now() / SECONDS_IN_DAY) as i64)
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl FromStr for Date {
+    type Err = Box<dyn Error>;
+
+    ///`YYYY-MM-DD`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid date \"{s}\", expected YYYY-MM-DD");
+        let mut parts = s.splitn(3, '-');
+        let mut part = || parts.next().ok_or_else(invalid);
+        let (year, month, day) = (part()?, part()?, part()?);
+        if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+            return Err(invalid().into());
+        }
+
+        let date = Date {
+            year: year.parse().map_err(|_| invalid())?,
+            month: month.parse().map_err(|_| invalid())?,
+            day: day.parse().map_err(|_| invalid())?,
+        };
+        //dates that do not exist do not survive the round trip
+        if Date::from_days(date.days()) != date {
+            return Err(invalid().into());
+        }
+        Ok(date)
+    }
+}
+
+///seconds since unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+///`YYYY-MM-DD HH:MM UTC`
+pub fn format_timestamp(seconds: u64) -> String {
+    let date = Date::from_days((seconds / SECONDS_IN_DAY) as i64);
+    let time = seconds % SECONDS_IN_DAY;
+    format!("{date} {:02}:{:02} UTC", time / 3600, time / 60 % 60)
+}
+
+///user running the program, recorded as the owner of new keys
+pub fn current_user() -> Option<String> {
+    ["USER", "USERNAME"]
+        .into_iter()
+        .find_map(|variable| std::env::var(variable).ok())
+        .filter(|user| !user.is_empty())
+}
+
+///what is known about a key besides the key itself
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub note: Option<String>,
+    pub tags: Vec<String>,
+    pub owner: Option<String>,
+    ///seconds since unix epoch, unknown for keys from older databases
+    pub created: Option<u64>,
+    pub modified: Option<u64>,
+    ///first day the key must not be used for encryption
+    pub expires: Option<Date>,
+}
+
+impl Metadata {
+    pub fn is_expired(&self, today: Date) -> bool {
+        self.expires.is_some_and(|expires| expires <= today)
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_timestamp, Date};
+
+    #[test]
+    fn dates() {
+        assert_eq!(Date::from_days(0).to_string(), "1970-01-01");
+        assert_eq!("2000-02-29".parse::<Date>().unwrap().days(), 11016);
+        for days in [-800000, -1, 0, 59, 60, 10957, 20000, 2932896] {
+            assert_eq!(Date::from_days(days).days(), days);
+        }
+
+        assert!("2027-01-01".parse::<Date>().unwrap() > "2026-12-31".parse().unwrap());
+        for invalid in [
+            "2023-02-29",
+            "2027-13-01",
+            "2027-1-01",
+            "27-01-01",
+            "2027-01",
+        ] {
+            assert!(invalid.parse::<Date>().is_err(), "{invalid}");
+        }
+
+        assert_eq!(format_timestamp(1_700_000_000), "2023-11-14 22:13 UTC");
+    }
+}
@@ -0,0 +1,115 @@
+//! Password protection of files at rest: a key is derived from the password
+//! with Argon2id and the content is sealed with ChaCha20-Poly1305
+
+use std::{error::Error, fmt::Display};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, Payload},
+    ChaCha20Poly1305, KeyInit, Nonce,
+};
+use rand::{thread_rng, RngCore};
+
+///starts sealed files, plain databases start with text instead
+const MAGIC: &[u8; 8] = b"PCDBENC1";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PasswordError {
+    Missing,
+    ///authentication failed: the password does not fit or the data was changed
+    Wrong,
+}
+
+impl Display for PasswordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PasswordError::Missing => write!(f, "database is encrypted, a PASSWORD is needed"),
+            PasswordError::Wrong => {
+                write!(f, "wrong password, or the encrypted database is damaged")
+            }
+        }
+    }
+}
+
+impl Error for PasswordError {}
+
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn seal(password: &str, content: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    thread_rng().fill_bytes(&mut salt);
+    thread_rng().fill_bytes(&mut nonce);
+
+    let mut sealed = MAGIC.to_vec();
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+
+    let encrypted = cipher(password, &salt)?
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: content,
+                aad: &sealed,
+            },
+        )
+        .map_err(|_| "failed to encrypt database")?;
+    sealed.extend(encrypted);
+    Ok(sealed)
+}
+
+pub fn open(password: &str, sealed: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let header_len = MAGIC.len() + SALT_LEN + NONCE_LEN;
+    if !is_sealed(sealed) || sealed.len() < header_len {
+        return Err("encrypted database is too short".into());
+    }
+    let (header, encrypted) = sealed.split_at(header_len);
+    let salt = &header[MAGIC.len()..MAGIC.len() + SALT_LEN];
+    let nonce = &header[MAGIC.len() + SALT_LEN..];
+
+    cipher(password, salt)?
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: encrypted,
+                aad: header,
+            },
+        )
+        .map_err(|_| PasswordError::Wrong.into())
+}
+
+fn cipher(password: &str, salt: &[u8]) -> Result<ChaCha20Poly1305, Box<dyn Error>> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("failed to derive key from password: {e}"))?;
+    Ok(ChaCha20Poly1305::new(&key.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_sealed, open, seal, PasswordError};
+
+    #[test]
+    fn round_trip() {
+        let sealed = seal("secret", b"keys").unwrap();
+        assert!(is_sealed(&sealed));
+        assert_eq!(open("secret", &sealed).unwrap(), b"keys");
+
+        let error = open("guess", &sealed).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<PasswordError>(),
+            Some(&PasswordError::Wrong)
+        );
+
+        let mut damaged = sealed.clone();
+        *damaged.last_mut().unwrap() ^= 1;
+        assert!(open("secret", &damaged).is_err());
+        assert!(open("secret", &sealed[..20]).is_err());
+    }
+}
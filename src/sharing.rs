@@ -0,0 +1,309 @@
+//! Threshold sharing of keys: the serialized key is split with Shamir's
+//! scheme over GF(256), any `threshold` shares rebuild it and fewer tell
+//! nothing about it. The checksum of the key is split together with the key,
+//! so that a single share does not carry anything computed from the key
+
+use std::{error::Error, io::BufWriter};
+
+use rand::{thread_rng, RngCore};
+use sha2::{Digest, Sha256};
+
+use crate::algorithms::{
+    serialization::{Deserializer, Serializer},
+    stacked::StackedCypher,
+};
+
+///starts share files
+const MAGIC: &[u8; 4] = b"PCS1";
+
+///hashed in front of every share file, to find damaged files
+const SHARE_DOMAIN: &[u8] = b"permutations key share\0";
+
+///hashed in front of the serialized key, to check what shares rebuild
+const SECRET_DOMAIN: &[u8] = b"permutations shared key\0";
+
+const CHECKSUM_LEN: usize = 8;
+
+///the key is checked more strictly than a file, it is the last line of defence
+const SECRET_CHECKSUM_LEN: usize = 16;
+
+///magic, split id, threshold, count and index
+const HEADER_LEN: usize = MAGIC.len() + 8 + 3;
+
+///one of the files produced by `split`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Share {
+    ///random, the same for all shares of one split
+    pub split: [u8; 8],
+    pub threshold: u8,
+    pub count: u8,
+    ///from 1, the point of the polynomial
+    pub index: u8,
+    pub data: Vec<u8>,
+}
+
+impl Share {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&self.split);
+        bytes.extend_from_slice(&[self.threshold, self.count, self.index]);
+        bytes.extend_from_slice(&self.data);
+        let checksum = checksum::<CHECKSUM_LEN>(SHARE_DOMAIN, &bytes);
+        bytes.extend_from_slice(&checksum);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Share, Box<dyn Error>> {
+        if !bytes.starts_with(MAGIC) {
+            return Err("not a key share".into());
+        }
+        if bytes.len() < HEADER_LEN + CHECKSUM_LEN {
+            return Err("key share is too short".into());
+        }
+        let (content, expected) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        if checksum::<CHECKSUM_LEN>(SHARE_DOMAIN, content) != expected {
+            return Err("key share is damaged, checksum does not match".into());
+        }
+
+        let (header, data) = content.split_at(HEADER_LEN);
+        //lengths are checked above
+        let share = Share {
+            split: header[4..12].try_into().unwrap(),
+            threshold: header[12],
+            count: header[13],
+            index: header[14],
+            data: data.to_vec(),
+        };
+        if share.index == 0 || share.index > share.count || share.threshold > share.count {
+            return Err("key share is damaged, invalid header".into());
+        }
+        Ok(share)
+    }
+}
+
+fn checksum<const N: usize>(domain: &[u8], content: &[u8]) -> [u8; N] {
+    let digest = Sha256::new()
+        .chain_update(domain)
+        .chain_update(content)
+        .finalize();
+    digest[..N].try_into().unwrap()
+}
+
+///`count` shares, any `threshold` of them rebuild the key
+pub fn split(
+    cypher: &StackedCypher,
+    count: usize,
+    threshold: usize,
+) -> Result<Vec<Share>, Box<dyn Error>> {
+    if threshold < 2 {
+        return Err("threshold must be at least 2, otherwise every share is the whole key".into());
+    }
+    if threshold > count {
+        return Err(format!("threshold {threshold} is more than {count} shares").into());
+    }
+    if count > u8::MAX as usize {
+        return Err(format!("at most {} shares are supported", u8::MAX).into());
+    }
+
+    let mut buffer = BufWriter::new(Vec::new());
+    //writing into memory cannot fail
+    Serializer::new(&mut buffer).write(cypher).unwrap();
+    let mut secret = buffer.into_inner().unwrap();
+    secret.extend(checksum::<SECRET_CHECKSUM_LEN>(SECRET_DOMAIN, &secret));
+
+    let mut split = [0u8; 8];
+    thread_rng().fill_bytes(&mut split);
+    let mut shares = (1..=count as u8)
+        .map(|index| Share {
+            split,
+            threshold: threshold as u8,
+            count: count as u8,
+            index,
+            data: Vec::with_capacity(secret.len()),
+        })
+        .collect::<Vec<_>>();
+
+    //every byte gets its own random polynomial with the byte as the constant term
+    let mut coefficients = vec![0u8; threshold];
+    for byte in secret {
+        coefficients[0] = byte;
+        thread_rng().fill_bytes(&mut coefficients[1..]);
+        for share in &mut shares {
+            let value = coefficients
+                .iter()
+                .rev()
+                .fold(0, |value, &c| gf_mul(value, share.index) ^ c);
+            share.data.push(value);
+        }
+    }
+    Ok(shares)
+}
+
+///rebuilds the key from at least `threshold` shares of the same split
+pub fn combine(shares: &[Share]) -> Result<StackedCypher, Box<dyn Error>> {
+    let first = shares.first().ok_or("no shares given")?;
+    for share in shares {
+        if (share.split, share.threshold, share.count)
+            != (first.split, first.threshold, first.count)
+        {
+            return Err(format!(
+                "share {} of {} belongs to another split",
+                share.index, share.count
+            )
+            .into());
+        }
+        if share.data.len() != first.data.len() {
+            return Err(format!("share {} has a wrong length", share.index).into());
+        }
+    }
+    let mut indices = shares.iter().map(|share| share.index).collect::<Vec<_>>();
+    indices.sort();
+    if let Some(twice) = indices.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(format!("share {} is given twice", twice[0]).into());
+    }
+    if shares.len() < first.threshold as usize {
+        return Err(format!(
+            "{} of {} shares are needed, only {} given",
+            first.threshold,
+            first.count,
+            shares.len()
+        )
+        .into());
+    }
+
+    //Lagrange interpolation at zero, subtraction is xor in GF(256)
+    let weights = shares
+        .iter()
+        .map(|share| {
+            shares
+                .iter()
+                .filter(|other| other.index != share.index)
+                .fold(1, |weight, other| {
+                    gf_mul(
+                        weight,
+                        gf_mul(other.index, gf_inverse(other.index ^ share.index)),
+                    )
+                })
+        })
+        .collect::<Vec<_>>();
+    let secret = (0..first.data.len())
+        .map(|i| {
+            shares
+                .iter()
+                .zip(&weights)
+                .fold(0, |byte, (share, &weight)| {
+                    byte ^ gf_mul(share.data[i], weight)
+                })
+        })
+        .collect::<Vec<_>>();
+
+    const CHANGED: &str = "shares do not rebuild the key, some of them were changed";
+    let (key, expected) = secret
+        .split_at_checked(secret.len().saturating_sub(SECRET_CHECKSUM_LEN))
+        .filter(|(key, _)| !key.is_empty())
+        .ok_or(CHANGED)?;
+    if checksum::<SECRET_CHECKSUM_LEN>(SECRET_DOMAIN, key) != expected {
+        return Err(CHANGED.into());
+    }
+    Deserializer::new(key).read().map_err(|_| CHANGED.into())
+}
+
+///multiplication modulo x^8 + x^4 + x^3 + x + 1
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+///a^254, as a^255 = 1 for every nonzero a
+fn gf_inverse(a: u8) -> u8 {
+    let mut result = 1;
+    let mut base = a;
+    let mut exponent = 254u32;
+    while exponent != 0 {
+        if exponent & 1 != 0 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::algorithms::{
+        fingerprint::Fingerprint,
+        permutation::SimplePermutation,
+        stacked::{EncryptionStyle::*, PadApproach::*, StackedCypher},
+    };
+
+    use super::{combine, gf_inverse, gf_mul, split, Share};
+
+    #[test]
+    fn field() {
+        assert_eq!(gf_mul(0x53, 0xca), 1);
+        for a in 1..=255 {
+            assert_eq!(gf_mul(a, gf_inverse(a)), 1, "{a}");
+        }
+    }
+
+    #[test]
+    fn any_threshold_shares_rebuild_the_key() {
+        let mut cypher = StackedCypher::new();
+        cypher.push(
+            Padding,
+            Char,
+            SimplePermutation::try_from(vec![3, 1, 0, 2]).unwrap(),
+        );
+        let shares = split(&cypher, 5, 3).unwrap();
+        let shares = shares
+            .iter()
+            .map(|share| Share::from_bytes(&share.to_bytes()).unwrap())
+            .collect::<Vec<_>>();
+
+        for picked in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let picked = picked.map(|i| shares[i].clone());
+            assert_eq!(combine(&picked).unwrap(), cypher);
+        }
+        assert_eq!(combine(&shares).unwrap(), cypher);
+
+        let error = combine(&shares[..2]).unwrap_err().to_string();
+        assert!(error.contains("only 2 given"), "{error}");
+        let twice = [shares[0].clone(), shares[1].clone(), shares[0].clone()];
+        assert!(combine(&twice).is_err());
+        let other = split(&cypher, 5, 3).unwrap();
+        let mixed = [shares[0].clone(), shares[1].clone(), other[2].clone()];
+        let error = combine(&mixed).unwrap_err().to_string();
+        assert!(error.contains("another split"), "{error}");
+
+        let mut changed = shares[..3].to_vec();
+        changed[1].data[0] ^= 1;
+        assert!(combine(&changed).is_err());
+
+        let mut damaged = shares[0].to_bytes();
+        damaged[30] ^= 1;
+        assert!(Share::from_bytes(&damaged).is_err());
+
+        //nothing computed from the key is stored next to a share
+        let fingerprint = Fingerprint::of(&cypher).0;
+        for share in &shares {
+            let bytes = share.to_bytes();
+            assert!(!bytes.windows(8).any(|window| window == fingerprint));
+            assert_eq!(bytes.len(), 4 + 8 + 3 + share.data.len() + 8);
+        }
+
+        assert!(split(&cypher, 3, 4).is_err());
+        assert!(split(&cypher, 3, 1).is_err());
+    }
+}